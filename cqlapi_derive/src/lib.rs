@@ -0,0 +1,331 @@
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::parse_macro_input;
+use syn::Attribute;
+use syn::Data;
+use syn::DeriveInput;
+use syn::Error;
+use syn::Fields;
+use syn::Ident;
+use syn::Lit;
+use syn::Meta;
+use syn::NestedMeta;
+use syn::Result;
+use syn::Type;
+
+
+// #[derive(Cql)]
+// #[cql(table = "person", by_entity = "union", by_many = "paper")]
+// struct Person {
+//     #[cql(substring)]
+//     first_name: String,
+//     #[cql(value, order = "asc")]
+//     tags: HashSet<String>,
+//     #[cql(value, timestamp)]
+//     born_at: i64,
+//     note: String
+// }
+//
+// fields without value or substring are storaged, the table defaults to the lowercase struct name
+
+enum Query {
+    Storaged,
+    Value,
+    Substring
+}
+
+struct FieldAttrs {
+    query: Query,
+    timestamp: bool,
+    order: Option<String>
+}
+
+#[proc_macro_derive(Cql, attributes(cql))]
+pub fn derive_cql(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into()
+    }
+}
+
+fn cql_metas(attrs: &[Attribute]) -> Result<Vec<NestedMeta>> {
+    let mut res = vec![];
+
+    for attr in attrs.iter() {
+        if !attr.path.is_ident("cql") {
+            continue;
+        }
+
+        match attr.parse_meta()? {
+            Meta::List(list) => res.extend(list.nested),
+            other => return Err(Error::new_spanned(other, "expected #[cql(...)]"))
+        }
+    }
+
+    Ok(res)
+}
+
+fn str_value(lit: &Lit) -> Result<String> {
+    match lit {
+        Lit::Str(s) => Ok(s.value()),
+        other => Err(Error::new_spanned(other, "expected a string"))
+    }
+}
+
+fn field_attrs(attrs: &[Attribute]) -> Result<FieldAttrs> {
+    let mut res = FieldAttrs {
+        query: Query::Storaged,
+        timestamp: false,
+        order: None
+    };
+
+    for meta in cql_metas(attrs)? {
+        match meta {
+            NestedMeta::Meta(Meta::Path(ref p)) if p.is_ident("value") => res.query = Query::Value,
+            NestedMeta::Meta(Meta::Path(ref p)) if p.is_ident("substring") => res.query = Query::Substring,
+            NestedMeta::Meta(Meta::Path(ref p)) if p.is_ident("timestamp") => res.timestamp = true,
+            NestedMeta::Meta(Meta::NameValue(ref nv)) if nv.path.is_ident("order") => {
+                let order = str_value(&nv.lit)?;
+
+                if order != "asc" && order != "desc" {
+                    return Err(Error::new_spanned(&nv.lit, "order is asc or desc"))
+                }
+
+                res.order = Some(order);
+            }
+            other => return Err(Error::new_spanned(other, "unknown cql field attribute"))
+        }
+    }
+
+    Ok(res)
+}
+
+// the last segment of a path type, String for ::std::string::String
+fn type_ident(ty: &Type) -> Option<String> {
+    match ty {
+        Type::Path(p) => p.path.segments.last().map(|s| s.ident.to_string()),
+        _ => None
+    }
+}
+
+// the rules ConfDef checks at runtime, caught at compile time: substring indexes text, lists and maps have no value index
+fn check_query(attrs: &FieldAttrs, ty: &Type) -> Result<()> {
+    let ident = type_ident(ty).unwrap_or_default();
+
+    match attrs.query {
+        Query::Substring if attrs.timestamp || ident != "String" => {
+            Err(Error::new_spanned(ty, "substring fields must be String"))
+        }
+        Query::Value if ident == "Vec" || ident == "HashMap" => {
+            Err(Error::new_spanned(ty, "Vec and HashMap fields can't be value fields"))
+        }
+        _ => Ok(())
+    }
+}
+
+fn expand(input: &DeriveInput) -> Result<TokenStream2> {
+    let name = &input.ident;
+
+    let mut table = name.to_string().to_lowercase();
+    let mut by_entity = vec![];
+    let mut by_many = vec![];
+
+    for meta in cql_metas(&input.attrs)? {
+        match meta {
+            NestedMeta::Meta(Meta::NameValue(ref nv)) if nv.path.is_ident("table") => table = str_value(&nv.lit)?,
+            NestedMeta::Meta(Meta::NameValue(ref nv)) if nv.path.is_ident("by_entity") => by_entity.push(str_value(&nv.lit)?),
+            NestedMeta::Meta(Meta::NameValue(ref nv)) if nv.path.is_ident("by_many") => by_many.push(str_value(&nv.lit)?),
+            other => return Err(Error::new_spanned(other, "unknown cql struct attribute"))
+        }
+    }
+
+    let fields = match input.data {
+        Data::Struct(ref s) => match s.fields {
+            Fields::Named(ref f) => f.named.iter().collect::<Vec<_>>(),
+            _ => return Err(Error::new_spanned(name, "Cql needs a struct with named fields"))
+        },
+        _ => return Err(Error::new_spanned(name, "Cql needs a struct with named fields"))
+    };
+
+    let mut field_confs = vec![];
+    let mut to_columns = vec![];
+    let mut from_columns = vec![];
+    let mut first_by = vec![];
+
+    for (i, f) in fields.iter().enumerate() {
+        let ident = f.ident.as_ref().unwrap();
+        let ty = &f.ty;
+        let field_name = ident.to_string();
+        let attrs = field_attrs(&f.attrs)?;
+
+        check_query(&attrs, ty)?;
+
+        let field = if attrs.timestamp {
+            quote! { ::cqlapi::Field::Timestamp(#field_name) }
+        } else {
+            quote! { <#ty as ::cqlapi::FieldType>::field(#field_name) }
+        };
+
+        let qt = match attrs.query {
+            Query::Storaged => quote! { ::cqlapi::QueryType::Storaged },
+            Query::Value => quote! { ::cqlapi::QueryType::Value },
+            Query::Substring => quote! { ::cqlapi::QueryType::Substring }
+        };
+
+        field_confs.push(match attrs.order.as_ref().map(|x| &x[..]) {
+            Some("asc") => quote! { ::cqlapi::new_ordered_fc(#field, #qt, ::cqlapi::Order::Asc) },
+            Some(_) => quote! { ::cqlapi::new_ordered_fc(#field, #qt, ::cqlapi::Order::Desc) },
+            None => quote! { ::cqlapi::new_fc(#field, #qt) }
+        });
+
+        if attrs.timestamp {
+            to_columns.push(quote! { ::cqlapi::Column::Timestamp(self.#ident) });
+            from_columns.push(quote! { #ident: ::cqlapi::timestamp_column(row, #field_name)? });
+        } else {
+            to_columns.push(quote! { ::cqlapi::ToColumn::to_column(&self.#ident) });
+            from_columns.push(quote! { #ident: ::cqlapi::column::<#ty>(row, #field_name)? });
+        }
+
+        if let Query::Storaged = attrs.query {
+            continue;
+        }
+
+        let method = Ident::new(&format!("first_by_{}", field_name), Span::call_site());
+
+        let key = if attrs.timestamp {
+            quote! { i64 }
+        } else {
+            quote! { <#ty as ::cqlapi::FieldType>::Key }
+        };
+
+        let key_column = if attrs.timestamp {
+            quote! { ::cqlapi::Column::Timestamp(value) }
+        } else {
+            quote! { ::cqlapi::ToColumn::to_column(&value) }
+        };
+
+        let fanned_out = if attrs.timestamp {
+            quote! { false }
+        } else {
+            quote! { <#ty as ::cqlapi::FieldType>::FANNED_OUT }
+        };
+
+        first_by.push(quote! {
+            pub fn #method(conn: &mut ::cqlapi::Session, group: i64, value: #key) -> ::std::result::Result<::std::option::Option<Self>, ::cqlapi::Error> {
+                let conf = Self::conf();
+
+                let row = {
+                    let fc = &conf.fields.as_ref().unwrap()[#i];
+
+                    conf.first(conn, group, fc, #key_column)?
+                };
+
+                match row {
+                    Some(row) => {
+                        if #fanned_out {
+                            // the row holds a single element of the set, the whole row is in the main table
+                            Self::first_by_id(conn, group, ::cqlapi::column::<i64>(&row, "id")?)
+                        } else {
+                            ::cqlapi::FromRow::from_row(&row, &[]).map(Some)
+                        }
+                    }
+                    None => Ok(None)
+                }
+            }
+        });
+    }
+
+    let names = fields.iter().map(|f| f.ident.as_ref().unwrap().to_string()).collect::<Vec<_>>();
+
+    let by_entity = if !by_entity.is_empty() {
+        quote! { conf.by_entity = Some(vec![#(#by_entity),*]); }
+    } else {
+        quote! {}
+    };
+
+    let by_many = if !by_many.is_empty() {
+        quote! { conf.by_many = Some(vec![#(#by_many),*]); }
+    } else {
+        quote! {}
+    };
+
+    Ok(quote! {
+        impl #name {
+            pub fn conf() -> ::cqlapi::Conf<'static, ()> {
+                let mut conf = ::cqlapi::new_conf(#table, (), Some(vec![#(#field_confs),*]), None, None);
+
+                #by_entity
+                #by_many
+
+                conf
+            }
+            // values in field order, as insert_all takes them
+            pub fn to_columns(&self) -> ::std::vec::Vec<::cqlapi::Column> {
+                vec![#(#to_columns),*]
+            }
+            pub fn to_hashmap(&self) -> ::std::collections::HashMap<::std::string::String, ::cqlapi::Column> {
+                let mut res = ::std::collections::HashMap::new();
+
+                #(res.insert(#names.to_string(), #to_columns);)*
+
+                res
+            }
+            pub fn first_by_id(conn: &mut ::cqlapi::Session, group: i64, id: i64) -> ::std::result::Result<::std::option::Option<Self>, ::cqlapi::Error> {
+                Self::conf().first_by_id_as(conn, group, id)
+            }
+            pub fn insert_all(&self, conn: &mut ::cqlapi::Session, group: i64, id: i64, consistency: ::cqlapi::Consistency, opts: &::cqlapi::WriteOptions) -> ::std::io::Result<::cqlapi::Response> {
+                Self::conf().insert_all(conn, group, id, self.to_columns(), consistency, opts)
+            }
+            #(#first_by)*
+        }
+
+        impl ::cqlapi::FromRow for #name {
+            // columns are looked up by name, the field order does not matter
+            fn from_row(row: &::std::collections::HashMap<::std::string::String, ::cqlapi::Column>, _fields: &[::cqlapi::FieldConf]) -> ::std::result::Result<Self, ::cqlapi::Error> {
+                Ok(#name {
+                    #(#from_columns),*
+                })
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse_quote;
+
+    fn attrs(query: Query, timestamp: bool) -> FieldAttrs {
+        FieldAttrs {
+            query,
+            timestamp,
+            order: None
+        }
+    }
+
+    #[test]
+    fn test_check_query() {
+        let string: Type = parse_quote!(String);
+        let int: Type = parse_quote!(i64);
+        let list: Type = parse_quote!(Vec<String>);
+        let map: Type = parse_quote!(::std::collections::HashMap<String, i64>);
+        let set: Type = parse_quote!(HashSet<String>);
+
+        assert!(check_query(&attrs(Query::Substring, false), &string).is_ok());
+        assert!(check_query(&attrs(Query::Substring, false), &int).is_err());
+        assert!(check_query(&attrs(Query::Substring, true), &int).is_err());
+
+        assert!(check_query(&attrs(Query::Value, false), &set).is_ok());
+        assert!(check_query(&attrs(Query::Value, true), &int).is_ok());
+        assert!(check_query(&attrs(Query::Value, false), &list).is_err());
+        assert!(check_query(&attrs(Query::Value, false), &map).is_err());
+
+        assert!(check_query(&attrs(Query::Storaged, false), &map).is_ok());
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use cqlapi::*;
+use cqlapi_derive::Cql;
+
+
+#[derive(Cql)]
+#[cql(table = "person", by_entity = "union")]
+struct Person {
+    #[cql(substring)]
+    first_name: String,
+    #[cql(value, order = "asc")]
+    tags: HashSet<String>,
+    #[cql(value, timestamp)]
+    born_at: i64,
+    note: String
+}
+
+fn get_person() -> Person {
+    Person {
+        first_name: "Ann".to_string(),
+        tags: vec!["a".to_string()].into_iter().collect(),
+        born_at: 1000,
+        note: "hi".to_string()
+    }
+}
+
+#[test]
+fn test_derived_conf() {
+    let conf = Person::conf();
+
+    assert_eq!(conf.name, "person");
+    assert_eq!(conf.by_entity, Some(vec!["union"]));
+    assert!(conf.by_many.is_none());
+
+    let fields = conf.fields.as_ref().unwrap();
+
+    assert_eq!(fields.iter().map(|fc| fc.f.get_name()).collect::<Vec<_>>(), vec!["first_name", "tags", "born_at", "note"]);
+
+    match (&fields[0].f, &fields[0].qt) {
+        (&Field::Text("first_name"), &QueryType::Substring) => {}
+        _ => panic!("first_name is a substring text field")
+    }
+
+    match (&fields[1].f, &fields[1].qt, &fields[1].order) {
+        (&Field::Set("tags", CqlType::Text), &QueryType::Value, &Some(Order::Asc)) => {}
+        _ => panic!("tags is an ascending value set")
+    }
+
+    match (&fields[2].f, &fields[2].qt) {
+        (&Field::Timestamp("born_at"), &QueryType::Value) => {}
+        _ => panic!("born_at is a timestamp value field")
+    }
+
+    match (&fields[3].f, &fields[3].qt) {
+        (&Field::Text("note"), &QueryType::Storaged) => {}
+        _ => panic!("note is storaged")
+    }
+}
+
+#[test]
+fn test_derived_columns() {
+    let p = get_person();
+
+    let columns = p.to_columns();
+
+    assert_eq!(columns, vec![
+        Column::String("Ann".to_string()),
+        Column::Set(vec![Column::String("a".to_string())]),
+        Column::Timestamp(1000),
+        Column::String("hi".to_string())
+    ]);
+
+    let row: HashMap<String, Column> = p.to_hashmap();
+
+    assert_eq!(row.len(), 4);
+    assert_eq!(row["born_at"], Column::Timestamp(1000));
+
+    let back = Person::from_row(&row, &[]).unwrap();
+
+    assert_eq!(back.first_name, "Ann");
+    assert_eq!(back.tags, p.tags);
+    assert_eq!(back.born_at, 1000);
+    assert_eq!(back.note, "hi");
+
+    let mut missing = row.clone();
+    missing.remove("note");
+
+    match Person::from_row(&missing, &[]) {
+        Err(Error::Missing(field)) => assert_eq!(field, "note"),
+        _ => panic!("note is not in the row")
+    }
+}
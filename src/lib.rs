@@ -1,6 +1,21 @@
 extern crate chrono;
 extern crate simpleflake;
 extern crate rustcql;
+#[cfg(feature = "json")]
+extern crate serde;
+#[cfg(feature = "json")]
+#[macro_use]
+extern crate serde_derive;
+#[cfg(feature = "json")]
+extern crate serde_json;
+#[cfg(feature = "config")]
+extern crate serde_yaml;
+#[cfg(feature = "config")]
+extern crate toml;
+#[cfg(feature = "async")]
+extern crate futures;
+#[cfg(feature = "async")]
+extern crate tokio;
 
 
 
@@ -19,9 +34,31 @@ pub use rustcql::shared::Column;
 
 pub use proto::shared::*;
 pub use proto::schema::*;
+pub use proto::session::*;
+pub use proto::retry::*;
+pub use proto::batch::*;
+pub use proto::bulk::*;
+pub use proto::pool::*;
+#[cfg(feature = "async")]
+pub use proto::nonblocking::*;
+pub use proto::udt::*;
+pub use proto::options::*;
+pub use proto::def::*;
+pub use proto::value::*;
+pub use proto::error::*;
+pub use proto::row::*;
+pub use proto::check::*;
+pub use proto::id::*;
+pub use proto::bucket::*;
+pub use proto::counter::*;
+#[cfg(feature = "json")]
+pub use proto::json::*;
 pub use proto::conf::*;
 pub use proto::conf::conf_schema::*;
 pub use proto::conf::conf_create::*;
 pub use proto::conf::conf_insert::*;
 pub use proto::conf::conf_first::*;
-pub use proto::conf::conf_list::*;
\ No newline at end of file
+pub use proto::conf::conf_list::*;
+pub use proto::conf::conf_collection::*;
+pub use proto::conf::conf_count::*;
+pub use proto::conf::conf_delete::*;
\ No newline at end of file
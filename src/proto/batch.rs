@@ -0,0 +1,237 @@
+use std::io::Result;
+use std::io::Error;
+use std::io::ErrorKind;
+
+use rustcql::shared::Consistency;
+use rustcql::shared::Response;
+use rustcql::shared::BatchQuery;
+use rustcql::shared::Column;
+
+use proto::session::Session;
+use proto::retry::answered;
+
+
+// cassandra's batch_size_warn_threshold_in_kb default
+pub static BATCH_SIZE_WARN: usize = 5 * 1024;
+
+#[derive(Clone, Copy)]
+pub enum BatchMode {
+    // one logged batch while it's no bigger than the limit, several logged batches above it,
+    // each of them is atomic but the write as a whole isn't anymore
+    Logged(usize),
+    // no atomicity, statements are grouped per partition into unlogged batches
+    Unlogged
+}
+
+pub enum Planned {
+    Logged(Vec<BatchQuery>),
+    Unlogged(Vec<BatchQuery>)
+}
+
+// approximate size the batch takes on the wire
+pub fn batch_size(batch: &Vec<BatchQuery>) -> usize {
+    batch.iter().fold(0, |size, bq| size + query_size(bq))
+}
+
+pub fn query_size(bq: &BatchQuery) -> usize {
+    match bq {
+        &BatchQuery::SimpleWithParams(ref query, ref values) => {
+            query.len() + values.iter().fold(0, |size, x| size + column_size(x))
+        }
+        &BatchQuery::Prepared(ref id, ref values) => {
+            id.len() + values.iter().fold(0, |size, x| size + column_size(x))
+        }
+        _ => 0
+    }
+}
+
+pub fn column_size(c: &Column) -> usize {
+    match c {
+        &Column::String(ref s) => 4 + s.len(),
+        &Column::List(ref items) | &Column::Set(ref items) => {
+            4 + items.iter().fold(0, |size, x| size + column_size(x))
+        }
+        _ => 4 + 8
+    }
+}
+
+pub fn plan(batch: Vec<BatchQuery>, mode: &BatchMode) -> Vec<Planned> {
+    match mode {
+        &BatchMode::Logged(max) => {
+            if batch_size(&batch) <= max {
+                return vec![Planned::Logged(batch)]
+            }
+
+            let mut res = vec![];
+            let mut chunk = vec![];
+            let mut size = 0;
+
+            for bq in batch.into_iter() {
+                let s = query_size(&bq);
+
+                if chunk.len() > 0 && size + s > max {
+                    res.push(Planned::Logged(chunk));
+                    chunk = vec![];
+                    size = 0;
+                }
+
+                size = size + s;
+                chunk.push(bq);
+            }
+
+            if chunk.len() > 0 {
+                res.push(Planned::Logged(chunk));
+            }
+
+            res
+        }
+        &BatchMode::Unlogged => {
+            let mut partitions: Vec<((String, Vec<Column>), Vec<BatchQuery>)> = vec![];
+
+            for bq in batch.into_iter() {
+                let key = partition_of(&bq);
+
+                match partitions.iter().position(|&(ref k, _)| *k == key) {
+                    Some(i) => partitions[i].1.push(bq),
+                    None => partitions.push((key, vec![bq]))
+                }
+            }
+
+            partitions.into_iter().map(|(_, b)| Planned::Unlogged(b)).collect()
+        }
+    }
+}
+
+// table and partition values of a generated statement
+pub fn partition_of(bq: &BatchQuery) -> (String, Vec<Column>) {
+    match bq {
+        &BatchQuery::SimpleWithParams(ref query, ref values) => {
+            let (start, count) = partition_bounds(query);
+
+            (table_of(query), values.iter().skip(start).take(count).cloned().collect())
+        }
+        _ => ("".to_string(), vec![])
+    }
+}
+
+// where the partition values are bound and how many there are, (group) or (group, bucket):
+// inserts start with them, updates and deletes have them first in their where clause
+pub fn partition_bounds(query: &str) -> (usize, usize) {
+    let count = if query.contains("(group,bucket,") || query.contains(" where group = ? and bucket = ?") { 2 } else { 1 };
+
+    match query.find(" where ") {
+        Some(i) => (query[..i].matches('?').count(), count),
+        None => (0, count)
+    }
+}
+
+pub fn table_of(query: &str) -> String {
+    let words: Vec<&str> = query.split_whitespace().collect();
+
+    let i = match words.iter().position(|x| *x == "into" || *x == "from" || *x == "update") {
+        Some(i) => i + 1,
+        None => return "".to_string()
+    };
+
+    match words.get(i) {
+        Some(name) => name.split('(').next().unwrap_or("").to_string(),
+        None => "".to_string()
+    }
+}
+
+// one statement with all the queries of the batch, values are bound in order,
+// the text only depends on the statements of the write so it is prepared once for writes of the same shape
+pub fn unlogged_query(batch: Vec<BatchQuery>) -> (String, Vec<Column>) {
+    let mut query = "begin unlogged batch ".to_string();
+    let mut values = vec![];
+
+    for bq in batch.into_iter() {
+        match bq {
+            BatchQuery::SimpleWithParams(q, v) => {
+                query = query + &q + "; ";
+                values.extend(v);
+            }
+            _ => panic!("only queries with params can go into an unlogged batch")
+        }
+    }
+
+    (query + "apply batch", values)
+}
+
+// an update of counter columns, it isn't a BatchQuery so it can't end up in a regular batch
+#[derive(Clone, Debug, PartialEq)]
+pub struct CounterUpdate {
+    pub query: String,
+    pub values: Vec<Column>
+}
+
+// counter updates can't share a batch with other statements
+pub fn counter_query(batch: Vec<CounterUpdate>) -> (String, Vec<Column>) {
+    if batch.len() == 1 {
+        let cu = batch[0].clone();
+
+        return (cu.query, cu.values)
+    }
+
+    let mut query = "begin counter batch ".to_string();
+    let mut values = vec![];
+
+    for cu in batch.into_iter() {
+        query = query + &cu.query + "; ";
+        values.extend(cu.values);
+    }
+
+    (query + "apply batch", values)
+}
+
+impl Session {
+    // counters go after the batch succeeded, they are never retried as replaying them counts twice,
+    // errors the server answers with come back as Err with a ServerError, as for reads
+    pub fn execute_with_counters(&mut self, batch: Vec<BatchQuery>, counters: Vec<CounterUpdate>, consistency: Consistency, mode: &BatchMode, idempotent: bool) -> Result<Response> {
+        let result = try!(self.execute_planned(batch, consistency.clone(), mode, idempotent));
+
+        if counters.len() == 0 {
+            return Ok(result)
+        }
+
+        self.execute_counters(counters, consistency)
+    }
+    pub fn execute_counters(&mut self, counters: Vec<CounterUpdate>, consistency: Consistency) -> Result<Response> {
+        if counters.len() == 0 {
+            return Err(Error::new(ErrorKind::InvalidInput, "empty batch"))
+        }
+
+        let (q, v) = counter_query(counters);
+
+        answered(try!(self.retrying(false, |s| s.prm_query(q.clone(), v.clone(), consistency.clone()))))
+    }
+    // idempotent parts are retried by the session's retry policy
+    pub fn execute_planned(&mut self, batch: Vec<BatchQuery>, consistency: Consistency, mode: &BatchMode, idempotent: bool) -> Result<Response> {
+        let mut last = None;
+
+        for p in plan(batch, mode).into_iter() {
+            let result = match p {
+                Planned::Logged(b) => try!(self.retrying(idempotent, |s| s.execute_batch_ref(&b, consistency.clone()))),
+                Planned::Unlogged(mut b) => {
+                    let (q, v) = if b.len() == 1 {
+                        match b.pop() {
+                            Some(BatchQuery::SimpleWithParams(q, v)) => (q, v),
+                            _ => panic!("only queries with params can go into an unlogged batch")
+                        }
+                    } else {
+                        unlogged_query(b)
+                    };
+
+                    try!(self.retrying(idempotent, |s| s.prm_query(q.clone(), v.clone(), consistency.clone())))
+                }
+            };
+
+            last = Some(try!(answered(result)));
+        }
+
+        match last {
+            Some(result) => Ok(result),
+            None => Err(Error::new(ErrorKind::InvalidInput, "empty batch"))
+        }
+    }
+}
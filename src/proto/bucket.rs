@@ -0,0 +1,169 @@
+use std::io::Result;
+use std::io::Error as IoError;
+use std::io::ErrorKind;
+use std::result;
+
+use chrono::*;
+
+use rustcql::shared::Column;
+
+use proto::conf::*;
+use proto::id::*;
+use proto::error::Error;
+
+
+// with bucketing the main and _by_field_ tables are partitioned by (group, bucket) instead of group,
+// the bucket comes from the id so every write knows it, reads go through the buckets one after the other
+//
+// Day and Month need simpleflake ids, writes reject ids from before 2001 as they can't be one,
+// reads look back that many days or months from the last id seen, or from now without one,
+// so first, count and list without a cursor miss rows older than the lookback window
+// Hash spreads ids over n shards, reads visit all of them
+
+#[derive(Clone, Copy)]
+pub enum Bucketing {
+    Day(u32),
+    Month(u32),
+    Hash(u32)
+}
+
+impl Bucketing {
+    pub fn bucket_of(&self, id: i64) -> i64 {
+        match self {
+            &Bucketing::Day(_) => id_millis(id).div_euclid(86400000),
+            &Bucketing::Month(_) => {
+                // the 41 bits of milliseconds since 2000 an id holds are all within the dates chrono holds
+                let t = id_timestamp(id).expect("an id time out of range");
+
+                t.year() as i64 * 12 + t.month0() as i64
+            }
+            &Bucketing::Hash(n) => (mix(id) % n as u64) as i64
+        }
+    }
+    // buckets in reading order, starting at the bucket of the last id seen
+    pub fn buckets_from(&self, last_id: Option<i64>) -> Vec<i64> {
+        match self {
+            &Bucketing::Day(lookback) | &Bucketing::Month(lookback) => {
+                let start = self.bucket_of(last_id.unwrap_or_else(|| id_at_time(&UTC::now())));
+
+                (0..lookback as i64).map(|i| start - i).collect()
+            }
+            &Bucketing::Hash(n) => {
+                let start = last_id.map_or(0, |id| self.bucket_of(id));
+
+                (start..n as i64).collect()
+            }
+        }
+    }
+    // buckets that can hold ids in [low, high), newest first, a range is at most lookback buckets long
+    pub fn buckets_between(&self, low: i64, high: i64, last_id: Option<i64>) -> Result<Vec<i64>> {
+        match self {
+            &Bucketing::Hash(_) => Ok(self.buckets_from(last_id)),
+            &Bucketing::Day(lookback) | &Bucketing::Month(lookback) => {
+                let end = self.bucket_of(low);
+
+                if self.bucket_of(high - 1) - end >= lookback as i64 {
+                    return Err(IoError::new(ErrorKind::InvalidInput, format!("a time range spans at most {} buckets", lookback)))
+                }
+
+                let start = self.bucket_of(last_id.unwrap_or(high - 1));
+
+                Ok((end..start + 1).rev().collect())
+            }
+        }
+    }
+    // ids from before 2001 can't be simpleflake ids, their rows would land in buckets no read looks at
+    pub fn check_id(&self, id: i64) -> result::Result<(), Error> {
+        match self {
+            &Bucketing::Hash(_) => Ok(()),
+            _ if id < id_at(SIMPLEFLAKE_EPOCH + 366 * 86400000) => Err(Error::NotSimpleflake(id)),
+            _ => Ok(())
+        }
+    }
+}
+
+impl<'a, T> Conf<'a, T> {
+    // partition key of the main and _by_field_ tables
+    pub fn partition_key(&self) -> &'static str {
+        match self.bucketing {
+            Some(_) => "(group,bucket)",
+            None => "group"
+        }
+    }
+    // leading columns of inserts, id follows
+    pub fn bucket_column(&self) -> &'static str {
+        match self.bucketing {
+            Some(_) => " bucket bigint,",
+            None => ""
+        }
+    }
+    pub fn partition_columns(&self) -> &'static str {
+        match self.bucketing {
+            Some(_) => "group,bucket,",
+            None => "group,"
+        }
+    }
+    pub fn partition_placeholders(&self) -> &'static str {
+        match self.bucketing {
+            Some(_) => "?,?,",
+            None => "?,"
+        }
+    }
+    pub fn partition_where(&self) -> &'static str {
+        match self.bucketing {
+            Some(_) => "group = ? and bucket = ?",
+            None => "group = ?"
+        }
+    }
+    pub fn partition_values(&self, group: i64, id: i64) -> Vec<Column> {
+        match self.bucketing {
+            Some(ref b) => vec![Column::Bigint(group), Column::Bigint(b.bucket_of(id))],
+            None => vec![Column::Bigint(group)]
+        }
+    }
+    // partition values of the rows, the last column is the id
+    pub fn key_values(&self, group: i64, id: i64) -> Vec<Column> {
+        let mut res = self.partition_values(group, id);
+
+        res.push(Column::Bigint(id));
+
+        res
+    }
+    // key_values in front of values, as the column lists of inserts have them
+    pub fn keyed(&self, group: i64, id: i64, values: Vec<Column>) -> Vec<Column> {
+        let mut res = self.key_values(group, id);
+
+        res.extend(values);
+
+        res
+    }
+    // partitions a read goes through, in order, each with the values of partition_where
+    pub fn read_partitions(&self, group: i64, buckets: Vec<i64>) -> Vec<Vec<Column>> {
+        match self.bucketing {
+            Some(_) => buckets.into_iter().map(|b| vec![Column::Bigint(group), Column::Bigint(b)]).collect(),
+            None => vec![vec![Column::Bigint(group)]]
+        }
+    }
+    pub fn buckets_from(&self, last_id: Option<i64>) -> Vec<i64> {
+        match self.bucketing {
+            Some(ref b) => b.buckets_from(last_id),
+            None => vec![]
+        }
+    }
+    pub fn check_id(&self, id: i64) -> result::Result<(), Error> {
+        match self.bucketing {
+            Some(ref b) => b.check_id(id),
+            None => Ok(())
+        }
+    }
+}
+
+// splitmix64 finalizer, simpleflake ids of a millisecond only differ in the low bits and sequential ids only in the lowest
+fn mix(id: i64) -> u64 {
+    let mut x = id as u64;
+
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+
+    x ^ (x >> 31)
+}
@@ -0,0 +1,215 @@
+use std::io::Result;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::mpsc::channel;
+use std::sync::mpsc::sync_channel;
+use std::thread;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+
+use rustcql::shared::Consistency;
+use rustcql::shared::BatchQuery;
+use rustcql::shared::Column;
+
+use proto::shared::*;
+use proto::conf::*;
+use proto::session::Session;
+use proto::batch::CounterUpdate;
+
+
+pub struct BulkOptions {
+    // ids are known to be fresh, no state is read so no index rows are deleted,
+    // the state is read row by row on the calling thread so loads that need the throughput set it
+    pub skip_state: bool,
+    // batches sent and not yet written, the reader waits for a writer once this many are out
+    pub concurrency: usize
+}
+
+pub fn new_bulk_options() -> BulkOptions {
+    BulkOptions {
+        skip_state: false,
+        concurrency: 16
+    }
+}
+
+pub struct BulkReport {
+    pub loaded: usize,
+    pub failed: Vec<(i64, i64, Error)>
+}
+
+impl BulkReport {
+    fn add(&mut self, group: i64, id: i64, result: Result<()>) {
+        match result {
+            Ok(()) => {
+                self.loaded = self.loaded + 1;
+            }
+            Err(e) => {
+                self.failed.push((group, id, e));
+            }
+        }
+    }
+}
+
+pub type BulkJob = (Vec<BatchQuery>, Vec<CounterUpdate>);
+
+// jobs are built on the calling thread, a row whose job fails to build is reported without reaching a writer,
+// each writer runs on its own thread, rows no writer is left for are reported as failed
+pub fn bulk_load<W, I>(writers: Vec<W>, jobs: I, concurrency: usize, progress: &mut FnMut(usize, usize)) -> BulkReport
+    where W: FnMut(BulkJob) -> Result<()> + Send + 'static, I: Iterator<Item = (i64, i64, Result<BulkJob>)> {
+
+    let concurrency = if concurrency == 0 { 1 } else { concurrency };
+
+    let (jobs_tx, jobs_rx) = sync_channel::<(i64, i64, BulkJob)>(concurrency);
+    let (results_tx, results_rx) = channel::<(i64, i64, Result<()>)>();
+
+    let jobs_rx = Arc::new(Mutex::new(jobs_rx));
+
+    let mut handles = vec![];
+
+    for mut write in writers.into_iter() {
+        let jobs_rx = jobs_rx.clone();
+        let results_tx = results_tx.clone();
+
+        handles.push(thread::spawn(move || {
+            loop {
+                let job = match jobs_rx.lock() {
+                    Ok(rx) => rx.recv(),
+                    Err(_) => break
+                };
+
+                match job {
+                    Ok((group, id, job)) => {
+                        // a writer that panics reports its row and stops, its session is in no known state
+                        let (result, dead) = match panic::catch_unwind(AssertUnwindSafe(|| write(job))) {
+                            Ok(result) => (result, false),
+                            Err(_) => (Err(Error::new(ErrorKind::Other, "writer panicked")), true)
+                        };
+
+                        if results_tx.send((group, id, result)).is_err() || dead {
+                            break;
+                        }
+                    }
+                    Err(_) => break
+                }
+            }
+        }));
+    }
+
+    // once the last writer stops the queue has no receiver left and sending fails instead of blocking
+    drop(jobs_rx);
+    drop(results_tx);
+
+    let mut report = BulkReport {
+        loaded: 0,
+        failed: vec![]
+    };
+
+    // rows sent to the writers and not reported yet
+    let mut pending: Vec<(i64, i64)> = vec![];
+
+    for (group, id, job) in jobs {
+        let job = match job {
+            Ok(job) => job,
+            Err(e) => {
+                report.failed.push((group, id, e));
+                progress(report.loaded, report.failed.len());
+                continue;
+            }
+        };
+
+        while pending.len() >= concurrency {
+            match results_rx.recv() {
+                Ok((group, id, result)) => finish(&mut report, &mut pending, group, id, result, progress),
+                Err(_) => break
+            }
+        }
+
+        if jobs_tx.send((group, id, job)).is_err() {
+            report.failed.push((group, id, no_writer()));
+            progress(report.loaded, report.failed.len());
+            continue;
+        }
+
+        pending.push((group, id));
+
+        while let Ok((group, id, result)) = results_rx.try_recv() {
+            finish(&mut report, &mut pending, group, id, result, progress);
+        }
+    }
+
+    drop(jobs_tx);
+
+    for (group, id, result) in results_rx.iter() {
+        finish(&mut report, &mut pending, group, id, result, progress);
+    }
+
+    for h in handles.into_iter() {
+        let _ = h.join();
+    }
+
+    // queued when the last writer stopped
+    for (group, id) in pending.into_iter() {
+        report.failed.push((group, id, no_writer()));
+        progress(report.loaded, report.failed.len());
+    }
+
+    report
+}
+
+fn finish(report: &mut BulkReport, pending: &mut Vec<(i64, i64)>, group: i64, id: i64, result: Result<()>, progress: &mut FnMut(usize, usize)) {
+    if let Some(i) = pending.iter().position(|&x| x == (group, id)) {
+        pending.swap_remove(i);
+    }
+
+    report.add(group, id, result);
+    progress(report.loaded, report.failed.len());
+}
+
+fn no_writer() -> Error {
+    Error::new(ErrorKind::Other, "no writer took the row")
+}
+
+impl<'a, T> Conf<'a, T> {
+    // rows are (group, id, values) as for insert_all, every writer session gets its own thread,
+    // reading the state of each row on reader is not spread over the writers, see skip_state,
+    // progress is called with the count of loaded and failed rows after every row that finishes
+    pub fn bulk_insert_all<I>(&self, reader: &mut Session, writers: Vec<Session>, rows: I, consistency: Consistency, opts: &WriteOptions, bulk: &BulkOptions, progress: &mut FnMut(usize, usize)) -> BulkReport
+        where I: Iterator<Item = (i64, i64, Vec<Column>)> {
+
+        let writers = writers.into_iter().map(|mut session| {
+            let consistency = consistency.clone();
+            let mode = opts.batch;
+
+            move |(batch, counters): BulkJob| {
+                session.execute_with_counters(batch, counters, consistency.clone(), &mode, true).map(|_| ())
+            }
+        }).collect::<Vec<_>>();
+
+        let jobs = rows.map(|(group, id, values)| (group, id, self.bulk_job(reader, group, id, values, opts, bulk)));
+
+        bulk_load(writers, jobs, bulk.concurrency, progress)
+    }
+    fn bulk_job(&self, reader: &mut Session, group: i64, id: i64, values: Vec<Column>, opts: &WriteOptions, bulk: &BulkOptions) -> Result<BulkJob> {
+        try!(self.check_id(id));
+
+        let mut values = values;
+
+        self.fill_all(&mut values);
+
+        let mut values = try!(self.check_all_values(values, opts));
+
+        let state = if bulk.skip_state {
+            None
+        } else {
+            let state = try!(self.first_by_id_with_deleted(reader, group, id));
+
+            self.revive_all(state, &mut values)
+        };
+
+        let counters = self.counters_for_all(group, &state, &values);
+
+        Ok((self.get_batch_for_insert_all(group, id, values, state, opts), counters))
+    }
+}
@@ -0,0 +1,94 @@
+use std::result;
+
+use rustcql::shared::Column;
+
+use proto::shared::*;
+use proto::conf::*;
+use proto::error::Error;
+
+
+// values are checked against their fields before any query is built
+// with coerce, bigints and timestamps convert into each other and bigints into doubles
+
+pub fn check_column(f: &Field, c: Column, coerce: bool) -> result::Result<Column, Error> {
+    let name = f.get_name();
+
+    match f {
+        &Field::Bigint(_) => check_element(&CqlType::Bigint, c, coerce, name),
+        &Field::Timestamp(_) => check_element(&CqlType::Timestamp, c, coerce, name),
+        &Field::Text(_) => check_element(&CqlType::Text, c, coerce, name),
+        &Field::Double(_) => check_element(&CqlType::Double, c, coerce, name),
+        &Field::Udt(_, udt) => check_element(&CqlType::Udt(udt), c, coerce, name),
+        &Field::List(_, ref t) => match c {
+            Column::List(items) => check_elements(t, items, coerce, name).map(Column::List),
+            _ => Err(Error::Type(name.to_string(), "list"))
+        },
+        &Field::Set(_, ref t) => match c {
+            Column::Set(items) => check_elements(t, items, coerce, name).map(Column::Set),
+            _ => Err(Error::Type(name.to_string(), "set"))
+        },
+        &Field::Map(_, ref k, ref v) => match c {
+            Column::Map(items) => {
+                let mut res = vec![];
+                for (key, value) in items.into_iter() {
+                    res.push((
+                        try!(check_element(k, key, coerce, name)),
+                        try!(check_element(v, value, coerce, name))
+                    ));
+                }
+                Ok(Column::Map(res))
+            }
+            _ => Err(Error::Type(name.to_string(), "map"))
+        }
+    }
+}
+
+fn check_elements(t: &CqlType, items: Vec<Column>, coerce: bool, name: &str) -> result::Result<Vec<Column>, Error> {
+    items.into_iter().map(|x| check_element(t, x, coerce, name)).collect()
+}
+
+fn check_element(t: &CqlType, c: Column, coerce: bool, name: &str) -> result::Result<Column, Error> {
+    match (t, c) {
+        (&CqlType::Bigint, c @ Column::Bigint(_)) => Ok(c),
+        (&CqlType::Timestamp, c @ Column::Timestamp(_)) => Ok(c),
+        (&CqlType::Double, c @ Column::Double(_)) => Ok(c),
+        (&CqlType::Text, c @ Column::String(_)) => Ok(c),
+        // udt values are json for fromJson(?)
+        (&CqlType::Udt(_), c @ Column::String(_)) => Ok(c),
+        (&CqlType::Bigint, Column::Timestamp(v)) if coerce => Ok(Column::Bigint(v)),
+        (&CqlType::Timestamp, Column::Bigint(v)) if coerce => Ok(Column::Timestamp(v)),
+        (&CqlType::Double, Column::Bigint(v)) if coerce => Ok(Column::Double(v as f64)),
+        _ => Err(Error::Type(name.to_string(), expected_type(t)))
+    }
+}
+
+pub fn expected_type(t: &CqlType) -> &'static str {
+    match t {
+        &CqlType::Bigint => "bigint",
+        &CqlType::Timestamp => "timestamp",
+        &CqlType::Text => "text",
+        &CqlType::Double => "double",
+        &CqlType::Udt(_) => "udt"
+    }
+}
+
+impl<'a, T> Conf<'a, T> {
+    pub fn check_values(&self, f: &[&FieldConf], values: Vec<Column>, opts: &WriteOptions) -> result::Result<Vec<Column>, Error> {
+        if values.len() > f.len() {
+            return Err(Error::Arity { expected: f.len(), got: values.len() });
+        }
+
+        if let Some(fc) = f.get(values.len()) {
+            return Err(Error::Missing(fc.f.get_name().to_string()));
+        }
+
+        f.iter().zip(values.into_iter()).map(|(fc, c)| check_column(&fc.f, c, opts.coerce)).collect()
+    }
+
+    pub fn check_all_values(&self, values: Vec<Column>, opts: &WriteOptions) -> result::Result<Vec<Column>, Error> {
+        match self.fields {
+            Some(ref fields) => self.check_values(&fields.iter().collect::<Vec<_>>(), values, opts),
+            None => self.check_values(&[], values, opts)
+        }
+    }
+}
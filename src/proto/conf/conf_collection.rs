@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+use std::io::Result;
+
+use chrono::*;
+
+use rustcql::Connection;
+use rustcql::shared::Consistency;
+use rustcql::shared::Response;
+use rustcql::shared::ResultBody;
+use rustcql::shared::Row;
+use rustcql::shared::BatchQuery;
+use rustcql::shared::Column;
+
+use proto::shared::*;
+
+use proto::jobs::jobs_create::*;
+use proto::jobs::jobs_insert::*;
+use proto::jobs::jobs_insert_all::*;
+use proto::jobs::jobs_delete::*;
+
+use proto::conf::*;
+use proto::schema::Schema;
+use proto::session::Session;
+use proto::batch::CounterUpdate;
+use proto::conf::conf_delete::is_deleted;
+use proto::udt::bind_nested;
+
+
+// (group id) f1 f2 ... fn                                  main
+// (group f1 id) f2 ... fn                                  Value
+// (group f1 id) f2 ... fn + (group f1_substring f1)        Substring
+// (group entity_id id) f1 f2 ... fn                        by_entity
+// (group entity_id id row) f1 f2 ... fn                    by_many
+
+// items is a Column::List, Column::Set or Column::Map for append,
+// for remove from a map it is a Column::Set of keys
+
+impl<'a, T> Conf<'a, T> {
+    pub fn append(&self, mut conn: &mut Session, group: i64, id: i64, fc: &FieldConf, items: Column, consistency: Consistency, opts: &WriteOptions) -> Result<Response> {
+        try!(self.check_id(id));
+
+        let state = if self.has_index_rows() || self.counters {
+            try!(self.first_by_id_with_deleted(&mut conn, group, id))
+        } else {
+            None
+        };
+
+        let counters = self.collection_counters(group, fc, &state, &items, true);
+
+        conn.execute_with_counters(self.get_batch_for_append(group, id, fc, items, state, opts), counters, consistency, &opts.batch, is_idempotent_append(fc))
+    }
+    pub fn remove(&self, mut conn: &mut Session, group: i64, id: i64, fc: &FieldConf, items: Column, consistency: Consistency, opts: &WriteOptions) -> Result<Response> {
+        let state = if self.has_index_rows() || self.counters {
+            try!(self.first_by_id_with_deleted(&mut conn, group, id))
+        } else {
+            None
+        };
+
+        let counters = self.collection_counters(group, fc, &state, &items, false);
+
+        conn.execute_with_counters(self.get_batch_for_remove(group, id, fc, items, state, opts), counters, consistency, &opts.batch, true)
+    }
+    // every field but the Storaged ones has a _by_field_ table with a copy of the row
+    pub fn has_index_rows(&self) -> bool {
+        self.fields.as_ref().map_or(false, |fields| fields.iter().any(|x| match x.qt {
+            QueryType::Storaged => false,
+            _ => true
+        }))
+    }
+    // only sets are indexed, the other collections count for the row being there,
+    // a soft deleted row stays deleted, it isn't counted
+    pub fn collection_counters(&self, group: i64, fc: &FieldConf, state: &Option<HashMap<String, Column>>, items: &Column, add: bool) -> Vec<CounterUpdate> {
+        if !self.counters || state.as_ref().map_or(false, is_deleted) {
+            return vec![];
+        }
+
+        let name = fc.f.get_name();
+
+        let mut row = match state {
+            &Some(ref state) => state.clone(),
+            &None if add => HashMap::new(),
+            &None => return vec![]
+        };
+
+        let old = row.get(name).map_or(vec![], |c| elements(c));
+
+        let new = match fc.f {
+            Field::Set(_, _) if add => {
+                let mut new = old.clone();
+                new.extend(elements(items).into_iter().filter(|x| !old.contains(x)));
+                Column::Set(new)
+            }
+            Field::Set(_, _) => Column::Set(removed_elements(&Column::Set(old), items)),
+            _ => row.get(name).cloned().unwrap_or(items.clone())
+        };
+
+        row.insert(name.to_string(), new);
+
+        self.count_jobs(group, state.as_ref(), Some(&row))
+    }
+    pub fn get_batch_for_append(&self, group: i64, id: i64, fc: &FieldConf, items: Column, state: Option<HashMap<String, Column>>, opts: &WriteOptions) -> Vec<BatchQuery> {
+
+        let opts = self.get_write_options(opts);
+
+        let mut batch = vec![];
+
+        // a soft deleted row stays deleted, it gets no index rows
+        if !state.as_ref().map_or(false, is_deleted) {
+            // None is a row that isn't there yet, the append creates it
+            let state = state.unwrap_or_else(HashMap::new);
+
+            if is_indexed_set(fc) {
+                // projection rows for the new elements, the rest of the row is taken from state
+                let field_name = fc.f.get_name();
+
+                let mut f: Vec<&FieldConf> = vec![];
+                let mut values: Vec<Column> = vec![];
+
+                if let Some(ref field_confs) = self.fields {
+                    for x in field_confs.iter() {
+                        if x.f.get_name() == field_name {
+                            f.push(x);
+                            values.push(items.clone());
+                        } else if let Some(c) = state.get(x.f.get_name()) {
+                            f.push(x);
+                            values.push(c.clone());
+                        }
+                    }
+                }
+
+                batch.extend(by_field_insert_job(self, &fc.f, &f, group, id, values, &opts));
+            }
+
+            batch.extend(self.index_copy_updates(fc, "+", &items, group, id, &state, &opts));
+        }
+
+        batch.push(self.collection_update(fc, "+", items, group, id, &opts));
+
+        batch
+    }
+    pub fn get_batch_for_remove(&self, group: i64, id: i64, fc: &FieldConf, items: Column, state: Option<HashMap<String, Column>>, opts: &WriteOptions) -> Vec<BatchQuery> {
+
+        let opts = self.get_write_options(opts);
+
+        let mut batch = vec![];
+
+        // a soft deleted row has no index rows left
+        if !state.as_ref().map_or(false, is_deleted) {
+            if is_indexed_set(fc) {
+                for e in elements(&items) {
+                    batch.push(by_field_delete_job(self, &fc.f, group, id, vec![], HashMap::new(), e, &opts));
+                }
+            }
+
+            if let Some(ref state) = state {
+                batch.extend(self.index_copy_updates(fc, "-", &items, group, id, state, &opts));
+            }
+        }
+
+        batch.push(self.collection_update(fc, "-", items, group, id, &opts));
+
+        batch
+    }
+    // the index rows of the other fields carry a copy of the collection, they are keyed by the values in state,
+    // the rows of fc itself hold a single element of it
+    fn index_copy_updates(&self, fc: &FieldConf, op: &str, items: &Column, group: i64, id: i64, state: &HashMap<String, Column>, opts: &WriteOptions) -> Vec<BatchQuery> {
+        let field_name = fc.f.get_name();
+
+        let mut batch = vec![];
+
+        if let Some(ref fields) = self.fields {
+            for x in fields.iter() {
+                if let QueryType::Storaged = x.qt {
+                    continue;
+                }
+
+                let name = x.f.get_name();
+
+                if name == field_name {
+                    continue;
+                }
+
+                let key = match state.get(name) {
+                    Some(c) => c.clone(),
+                    None => continue
+                };
+
+                let query = "update test1.".to_string() + self.name + "_by_field_" + name + &opts.using() + " set " + field_name + " = " + field_name + " " + op + " " + placeholder(&fc.f) + " where " + self.partition_where() + " and " + name + " = " + placeholder(&x.f) + " and id = ?";
+
+                let keys = match x.f {
+                    Field::Set(_, _) => elements(&key),
+                    _ => vec![key]
+                };
+
+                for key in keys.into_iter() {
+                    let mut values = vec![bind_nested(&fc.f, items.clone())];
+
+                    values.extend(self.partition_values(group, id));
+                    values.push(key);
+                    values.push(Column::Bigint(id));
+
+                    batch.push(BatchQuery::SimpleWithParams(query.clone(), opts.using_then_values(values)));
+                }
+            }
+        }
+
+        batch
+    }
+    fn collection_update(&self, fc: &FieldConf, op: &str, items: Column, group: i64, id: i64, opts: &WriteOptions) -> BatchQuery {
+        let field_name = fc.f.get_name();
+
+        let query = "update test1.".to_string() + self.name + &opts.using() + " set " + field_name + " = " + field_name + " " + op + " " + placeholder(&fc.f) + " where " + self.partition_where() + " and id = ?";
+
+        //println!("{}", query);
+
+        let mut values = vec![bind_nested(&fc.f, items)];
+
+        values.extend(self.key_values(group, id));
+
+        BatchQuery::SimpleWithParams(query, opts.using_then_values(values))
+    }
+}
+
+pub fn is_indexed_set(fc: &FieldConf) -> bool {
+    match (&fc.f, &fc.qt) {
+        (&Field::Set(_, _), &QueryType::Value) | (&Field::Set(_, _), &QueryType::Substring) => true,
+        _ => false
+    }
+}
+
+// list elements get server side keys, appending twice appends twice
+pub fn is_idempotent_append(fc: &FieldConf) -> bool {
+    match fc.f {
+        Field::List(_, _) => false,
+        _ => true
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+use std::io::Result;
+
+use rustcql::shared::Consistency;
+use rustcql::shared::Column;
+
+use proto::shared::*;
+
+use proto::conf::*;
+use proto::conf::conf_first::select_first;
+use proto::session::Session;
+use proto::batch::CounterUpdate;
+
+
+// (group id) f1 f2 ... fn                                  main
+// (group f1 id) f2 ... fn                                  Value
+// (group) count                                            counters
+// (group f1) count                                         counters by Value
+
+// count and count_by_field are exact, they scan the partitions, with time bucketing only the lookback window,
+// counted and counted_by_field read the counter tables the writes maintain when counters is on
+// with soft_delete count and count_by_field include the deleted rows still in the partitions, counted doesn't,
+// a row is uncounted when it is deleted and counted again when it is restored
+// the counter deltas are approximate, an insert reads the row before it writes, two concurrent inserts of a new id
+// both see no row and both count it, use count where the number has to be exact
+
+impl<'a, T> Conf<'a, T> {
+    pub fn count(&self, conn: &mut Session, group: i64) -> Result<i64> {
+        sum_counts(conn, self.count_queries(group))
+    }
+
+    pub fn count_by_field(&self, conn: &mut Session, group: i64, fc: &FieldConf, value: Column) -> Result<i64> {
+        sum_counts(conn, self.count_by_field_queries(group, fc, value))
+    }
+
+    pub fn counted(&self, conn: &mut Session, group: i64) -> Result<i64> {
+        let query = "select count from test1.".to_string() + self.name + "_counts where group = ?";
+
+        let row = try!(select_first(conn, vec![(query, vec![Column::Bigint(group)])]));
+
+        Ok(row.as_ref().and_then(count_of).unwrap_or(0))
+    }
+
+    pub fn counted_by_field(&self, conn: &mut Session, group: i64, fc: &FieldConf, value: Column) -> Result<i64> {
+        let field_name = fc.f.get_name();
+
+        let query = "select count from test1.".to_string() + self.name + "_counts_by_field_" + field_name + " where group = ? and " + field_name + " = " + placeholder(&fc.f);
+
+        let row = try!(select_first(conn, vec![(query, vec![Column::Bigint(group), value])]));
+
+        Ok(row.as_ref().and_then(count_of).unwrap_or(0))
+    }
+
+    pub fn count_queries(&self, group: i64) -> Vec<(String, Vec<Column>)> {
+        let query = "select count(*) from test1.".to_string() + self.name + " where " + self.partition_where();
+
+        self.read_partitions(group, self.buckets_from(None)).into_iter().map(|values| (query.clone(), values)).collect()
+    }
+
+    pub fn count_by_field_queries(&self, group: i64, fc: &FieldConf, value: Column) -> Vec<(String, Vec<Column>)> {
+        let field_name = fc.f.get_name();
+
+        let query = "select count(*) from test1.".to_string() + self.name + "_by_field_" + field_name + " where " + self.partition_where() + " and " + field_name + " = " + placeholder(&fc.f);
+
+        self.read_partitions(group, self.buckets_from(None)).into_iter().map(|mut values| {
+            values.push(value.clone());
+
+            (query.clone(), values)
+        }).collect()
+    }
+
+    // counter updates for writing values of f over state
+    pub fn counters_for(&self, group: i64, state: &Option<HashMap<String, Column>>, f: &[&FieldConf], values: &[Column]) -> Vec<CounterUpdate> {
+        if !self.counters {
+            return vec![];
+        }
+
+        self.count_jobs(group, state.as_ref(), Some(&overlay(state, f, values)))
+    }
+
+    pub fn counters_for_all(&self, group: i64, state: &Option<HashMap<String, Column>>, values: &[Column]) -> Vec<CounterUpdate> {
+        match self.fields {
+            Some(ref fields) => self.counters_for(group, state, &fields.iter().collect::<Vec<_>>(), values),
+            None => self.counters_for(group, state, &[], values)
+        }
+    }
+
+    // counter updates for a row going from old to new, None is a row that isn't there
+    pub fn count_jobs(&self, group: i64, old: Option<&HashMap<String, Column>>, new: Option<&HashMap<String, Column>>) -> Vec<CounterUpdate> {
+        let mut batch = vec![];
+
+        if !self.counters {
+            return batch;
+        }
+
+        let table = "test1.".to_string() + self.name + "_counts";
+
+        match (old.is_some(), new.is_some()) {
+            (false, true) => batch.push(counter_update(&table, "+", "", vec![Column::Bigint(group)])),
+            (true, false) => batch.push(counter_update(&table, "-", "", vec![Column::Bigint(group)])),
+            _ => {}
+        }
+
+        if let Some(ref fields) = self.fields {
+            for fc in fields.iter() {
+                if let QueryType::Storaged = fc.qt {
+                    continue;
+                }
+
+                let field_name = fc.f.get_name();
+
+                let old_keys = old.and_then(|x| x.get(field_name)).map_or(vec![], |c| index_keys(&fc.f, c));
+                let new_keys = new.and_then(|x| x.get(field_name)).map_or(vec![], |c| index_keys(&fc.f, c));
+
+                let table = "test1.".to_string() + self.name + "_counts_by_field_" + field_name;
+                let and = " and ".to_string() + field_name + " = " + placeholder(&fc.f);
+
+                for k in old_keys.iter().filter(|x| !new_keys.contains(x)) {
+                    batch.push(counter_update(&table, "-", &and, vec![Column::Bigint(group), k.clone()]));
+                }
+
+                for k in new_keys.iter().filter(|x| !old_keys.contains(x)) {
+                    batch.push(counter_update(&table, "+", &and, vec![Column::Bigint(group), k.clone()]));
+                }
+            }
+        }
+
+        batch
+    }
+}
+
+fn counter_update(table: &str, op: &str, and: &str, values: Vec<Column>) -> CounterUpdate {
+    let query = "update ".to_string() + table + " set count = count " + op + " 1 where group = ?" + and;
+
+    CounterUpdate { query: query, values: values }
+}
+
+// values a row is indexed under in a _by_field_ table
+fn index_keys(f: &Field, c: &Column) -> Vec<Column> {
+    match f {
+        &Field::Set(_, _) => elements(c),
+        _ => vec![c.clone()]
+    }
+}
+
+// state with the new values over it
+pub fn overlay(state: &Option<HashMap<String, Column>>, f: &[&FieldConf], values: &[Column]) -> HashMap<String, Column> {
+    let mut row = match state {
+        &Some(ref state) => state.clone(),
+        &None => HashMap::new()
+    };
+
+    for (fc, v) in f.iter().zip(values.iter()) {
+        row.insert(fc.f.get_name().to_string(), v.clone());
+    }
+
+    row
+}
+
+pub fn count_of(row: &HashMap<String, Column>) -> Option<i64> {
+    match row.get("count") {
+        Some(&Column::Bigint(v)) => Some(v),
+        _ => None
+    }
+}
+
+fn sum_counts(conn: &mut Session, queries: Vec<(String, Vec<Column>)>) -> Result<i64> {
+    let mut res = 0;
+
+    for (query, values) in queries.into_iter() {
+        let row = try!(select_first(conn, vec![(query, values)]));
+
+        res = res + row.as_ref().and_then(count_of).unwrap_or(0);
+    }
+
+    Ok(res)
+}
@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 use std::io::Result;
+use std::result;
 
 use chrono::*;
 
@@ -20,6 +21,9 @@ use proto::jobs::jobs_delete::*;
 
 use proto::conf::*;
 use proto::schema::Schema;
+use proto::udt::*;
+use proto::options::*;
+use proto::error::Error;
 
 
 // (group id) f1 f2 ... fn                                  main
@@ -29,10 +33,14 @@ use proto::schema::Schema;
 // (group entity_id id row) f1 f2 ... fn                    by_many
 
 impl<'a, T> Conf<'a, T> {
-    pub fn create(&self, schema: &mut Schema) {
+    pub fn create(&self, schema: &mut Schema) -> result::Result<(), Error> {
 
-        let mut query = "create table ".to_string() + self.name + " (group bigint,
-        id bigint,";
+        if let Some(ref types) = self.types {
+            try!(types_create_job(schema, types));
+        }
+
+        let mut query = "create table ".to_string() + self.name + " (group bigint," + self.bucket_column() + "
+        id bigint," + self.deleted_column();
 
         match self.fields {
             Some(ref f) => {
@@ -44,6 +52,10 @@ impl<'a, T> Conf<'a, T> {
             _ => {}
         }
 
+        if self.counters {
+            counters_create_job(self, schema);
+        }
+
         match self.by_entity {
             Some(ref fields) => {
                 for x in fields.iter() {
@@ -62,13 +74,35 @@ impl<'a, T> Conf<'a, T> {
             _ => {}
         }
 
-        query = query + "primary key (group, id)
+        query = query + "primary key (" + self.partition_key() + ", id)
         )
-        with clustering order by (id desc) and gc_grace_seconds = 86400";
+        with clustering order by (id desc) and " + &self.get_table_options(self.name);
 
         //println!("");
         //println!("{}", query);
 
         schema.queries.push(query);
+
+        Ok(())
+    }
+    // counter tables take no default_time_to_live
+    pub fn get_counter_table_options(&self, table: &str) -> String {
+        if let Some(ref overrides) = self.overrides {
+            if let Some(&(_, ref o)) = overrides.iter().find(|&&(name, _)| name == table) {
+                return o.render_counter_over(&self.options)
+            }
+        }
+
+        self.options.render_counter()
+    }
+    // table is the generated table name without keyspace, e.g. person_by_field_name
+    pub fn get_table_options(&self, table: &str) -> String {
+        if let Some(ref overrides) = self.overrides {
+            if let Some(&(_, ref o)) = overrides.iter().find(|&&(name, _)| name == table) {
+                return o.render_over(&self.options)
+            }
+        }
+
+        self.options.render()
     }
 }
\ No newline at end of file
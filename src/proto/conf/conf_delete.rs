@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+use std::io::Result;
+use std::io::Error;
+use std::io::ErrorKind;
+
+use rustcql::shared::Consistency;
+use rustcql::shared::Response;
+use rustcql::shared::BatchQuery;
+use rustcql::shared::Column;
+
+use proto::shared::*;
+
+use proto::jobs::jobs_insert::*;
+use proto::jobs::jobs_delete::*;
+
+use proto::conf::*;
+use proto::session::Session;
+
+
+// (group id) f1 f2 ... fn                                  main
+// (group f1 id) f2 ... fn                                  Value
+// (group f1 id) f2 ... fn + (group f1_substring f1)        Substring
+// (group entity_id id) f1 f2 ... fn                        by_entity
+// (group entity_id id row) f1 f2 ... fn                    by_many
+
+// with soft_delete on, delete stamps deleted_at on the main row and drops its index rows,
+// restore writes the index rows back from the main row, purge always deletes for good
+
+impl<'a, T> Conf<'a, T> {
+    // the main row and the index rows state points to
+    pub fn delete(&self, mut conn: &mut Session, group: i64, id: i64, consistency: Consistency, opts: &WriteOptions) -> Result<Response> {
+        let state = try!(self.first_by_id(&mut conn, group, id));
+
+        // a soft delete is an update, without a row it would write one
+        if self.soft_delete && state.is_none() {
+            return Err(Error::new(ErrorKind::NotFound, "no row to delete"));
+        }
+
+        let counters = self.count_jobs(group, state.as_ref(), None);
+
+        conn.execute_with_counters(self.get_batch_for_delete(group, id, state, opts), counters, consistency, &opts.batch, true)
+    }
+    pub fn purge(&self, mut conn: &mut Session, group: i64, id: i64, consistency: Consistency, opts: &WriteOptions) -> Result<Response> {
+        let state = try!(self.first_by_id_with_deleted(&mut conn, group, id));
+
+        // a soft deleted row was uncounted when it was deleted
+        let counters = match state {
+            Some(ref row) if !is_deleted(row) => self.count_jobs(group, Some(row), None),
+            _ => vec![]
+        };
+
+        conn.execute_with_counters(self.get_batch_for_purge(group, id, state, opts), counters, consistency, &opts.batch, true)
+    }
+    pub fn restore(&self, mut conn: &mut Session, group: i64, id: i64, consistency: Consistency, opts: &WriteOptions) -> Result<Response> {
+        let row = match try!(self.first_by_id_with_deleted(&mut conn, group, id)) {
+            Some(ref row) if is_deleted(row) => row.clone(),
+            _ => return Err(Error::new(ErrorKind::NotFound, "no deleted row to restore"))
+        };
+
+        let counters = self.count_jobs(group, None, Some(&row));
+
+        conn.execute_with_counters(self.get_batch_for_restore(group, id, row, opts), counters, consistency, &opts.batch, true)
+    }
+    pub fn get_batch_for_delete(&self, group: i64, id: i64, state: Option<HashMap<String, Column>>, opts: &WriteOptions) -> Vec<BatchQuery> {
+        if !self.soft_delete {
+            return self.get_batch_for_purge(group, id, state, opts);
+        }
+
+        let opts = self.get_write_options(opts);
+
+        let mut batch = self.index_delete_jobs(group, id, state, &opts);
+
+        // deleted_at is in ms like every timestamp column, the write timestamp is in µs
+        let deleted_at = opts.timestamp.unwrap_or_else(next_micros) / 1000;
+
+        let query = "update test1.".to_string() + self.name + &opts.using_timestamp() + " set deleted_at = ? where " + self.partition_where() + " and id = ?";
+
+        let mut values = vec![Column::Timestamp(deleted_at)];
+
+        values.extend(self.key_values(group, id));
+
+        batch.push(BatchQuery::SimpleWithParams(query, opts.using_then_values(values)));
+
+        batch
+    }
+    pub fn get_batch_for_purge(&self, group: i64, id: i64, state: Option<HashMap<String, Column>>, opts: &WriteOptions) -> Vec<BatchQuery> {
+
+        let opts = self.get_write_options(opts);
+
+        let mut batch = self.index_delete_jobs(group, id, state, &opts);
+
+        let query = "delete from test1.".to_string() + self.name + &opts.using_timestamp() + " where " + self.partition_where() + " and id = ?";
+
+        batch.push(BatchQuery::SimpleWithParams(query, opts.using_then_values(self.key_values(group, id))));
+
+        batch
+    }
+    // index rows of the stored row and deleted_at cleared, the main row itself is left as it is
+    pub fn get_batch_for_restore(&self, group: i64, id: i64, row: HashMap<String, Column>, opts: &WriteOptions) -> Vec<BatchQuery> {
+
+        let opts = self.get_write_options(opts);
+
+        let mut f: Vec<&FieldConf> = vec![];
+        let mut values: Vec<Column> = vec![];
+
+        if let Some(ref fields) = self.fields {
+            for fc in fields.iter() {
+                if let Some(c) = row.get(fc.f.get_name()) {
+                    f.push(fc);
+                    values.push(c.clone());
+                }
+            }
+        }
+
+        let mut batch = vec![];
+
+        for x in f.iter() {
+            batch.extend(field_insert_job(self, x, &f, group, id, values.clone(), &opts));
+        }
+
+        let query = "update test1.".to_string() + self.name + &opts.using_timestamp() + " set deleted_at = null where " + self.partition_where() + " and id = ?";
+
+        batch.push(BatchQuery::SimpleWithParams(query, opts.using_then_values(self.key_values(group, id))));
+
+        batch
+    }
+    // a write to a soft deleted id brings the row back with its stored columns: they join the fields of the write
+    // so every index row is written again, and the deleted row counts as no row at all
+    pub fn revive<'b>(&'b self, state: Option<HashMap<String, Column>>, f: &mut Vec<&'b FieldConf<'b>>, values: &mut Vec<Column>, nulls: &[&FieldConf]) -> Option<HashMap<String, Column>> {
+        let row = match state {
+            Some(row) => row,
+            None => return None
+        };
+
+        if !is_deleted(&row) {
+            return Some(row);
+        }
+
+        if let Some(ref fields) = self.fields {
+            for fc in fields.iter() {
+                let name = fc.f.get_name();
+
+                if f.iter().chain(nulls.iter()).any(|x| x.f.get_name() == name) {
+                    continue;
+                }
+
+                if let Some(c) = row.get(name) {
+                    f.push(fc);
+                    values.push(c.clone());
+                }
+            }
+        }
+
+        None
+    }
+    // revive for insert_all, its values are every field so nothing of the deleted row is kept
+    pub fn revive_all(&self, state: Option<HashMap<String, Column>>, values: &mut Vec<Column>) -> Option<HashMap<String, Column>> {
+        let mut f = self.fields.iter().flat_map(|x| x.iter()).collect::<Vec<_>>();
+
+        self.revive(state, &mut f, values, &[])
+    }
+    fn index_delete_jobs(&self, group: i64, id: i64, state: Option<HashMap<String, Column>>, opts: &WriteOptions) -> Vec<BatchQuery> {
+        let mut batch = vec![];
+
+        if let (Some(state), Some(fields)) = (state, self.fields.as_ref()) {
+            for fc in fields.iter() {
+                if let Some(c) = state.get(fc.f.get_name()) {
+                    // every element of a set goes
+                    batch.extend(field_delete_job(self, fc, group, id, vec![], state.clone(), c.clone(), &Column::Set(vec![]), opts));
+                }
+            }
+        }
+
+        batch
+    }
+    // the main table column of soft deletes, it follows id
+    pub fn deleted_column(&self) -> &'static str {
+        if self.soft_delete { " deleted_at timestamp," } else { "" }
+    }
+    // inserts clear deleted_at, writing to a soft deleted id brings the row back
+    pub fn deleted_insert_column(&self) -> &'static str {
+        if self.soft_delete { "deleted_at," } else { "" }
+    }
+    pub fn deleted_insert_value(&self) -> &'static str {
+        if self.soft_delete { "null," } else { "" }
+    }
+}
+
+pub fn is_deleted(row: &HashMap<String, Column>) -> bool {
+    match row.get("deleted_at") {
+        Some(&Column::Timestamp(_)) => true,
+        _ => false
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+
+use rustcql::shared::Column;
+
+use proto::shared::*;
+
+use proto::conf::*;
+
+
+// (group id) f1 f2 ... fn                                  main
+// (group f1 id) f2 ... fn                                  Value
+// (group f1 id) f2 ... fn + (group f1_substring f1)        Substring
+// (group entity_id id) f1 f2 ... fn                        by_entity
+// (group entity_id id row) f1 f2 ... fn                    by_many
+
+impl<'a, T> Conf<'a, T> {
+    // adds the filled fields to f and values, the row they see is state with the new values over it
+    // and without the nulled fields, which get no default either
+    pub fn fill<'b>(&'b self, f: &mut Vec<&'b FieldConf<'b>>, values: &mut Vec<Column>, nulls: &[&FieldConf], state: &Option<HashMap<String, Column>>) {
+        let fields = match self.fields {
+            Some(ref fields) => fields,
+            None => return
+        };
+
+        let mut row = match state {
+            &Some(ref state) => state.clone(),
+            &None => HashMap::new()
+        };
+
+        for (fc, v) in f.iter().zip(values.iter()) {
+            row.insert(fc.f.get_name().to_string(), v.clone());
+        }
+
+        for fc in nulls.iter() {
+            row.remove(fc.f.get_name());
+        }
+
+        // defaults first, computed fields may be made of them
+        for fc in fields.iter() {
+            let name = fc.f.get_name();
+
+            if row.contains_key(name) || nulls.iter().any(|n| n.f.get_name() == name) {
+                continue;
+            }
+
+            let c = match fc.fill {
+                Some(Fill::Default(ref c)) => c.clone(),
+                Some(Fill::Generated(ref g)) => g(&row),
+                _ => continue
+            };
+
+            row.insert(name.to_string(), c.clone());
+            f.push(fc);
+            values.push(c);
+        }
+
+        for fc in fields.iter() {
+            if let Some(Fill::Computed(ref g)) = fc.fill {
+                let name = fc.f.get_name();
+                let c = g(&row);
+
+                row.insert(name.to_string(), c.clone());
+
+                match f.iter().position(|x| x.f.get_name() == name) {
+                    Some(i) => values[i] = c,
+                    None => {
+                        f.push(fc);
+                        values.push(c);
+                    }
+                }
+            }
+        }
+    }
+
+    // insert_all has a value for every field, only computed ones are replaced
+    pub fn fill_all(&self, values: &mut Vec<Column>) {
+        let fields = match self.fields {
+            Some(ref fields) if fields.len() == values.len() => fields,
+            _ => return
+        };
+
+        let mut row = HashMap::new();
+
+        for (fc, v) in fields.iter().zip(values.iter()) {
+            row.insert(fc.f.get_name().to_string(), v.clone());
+        }
+
+        for (i, fc) in fields.iter().enumerate() {
+            if let Some(Fill::Computed(ref g)) = fc.fill {
+                let c = g(&row);
+
+                row.insert(fc.f.get_name().to_string(), c.clone());
+                values[i] = c;
+            }
+        }
+    }
+}
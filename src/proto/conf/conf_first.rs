@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 use std::io::Result;
+use std::result;
 
 use chrono::*;
 
@@ -20,6 +21,10 @@ use proto::jobs::jobs_delete::*;
 
 use proto::conf::*;
 use proto::schema::Schema;
+use proto::session::Session;
+use proto::row::FromRow;
+use proto::error::Error;
+use proto::conf::conf_delete::is_deleted;
 
 
 // (group id) f1 f2 ... fn                                  main
@@ -29,71 +34,109 @@ use proto::schema::Schema;
 // (group entity_id id row) f1 f2 ... fn                    by_many
 
 impl<'a, T> Conf<'a, T> {
-    pub fn first(&self, conn: &mut Connection, group: i64, fc: &FieldConf, key: Column) -> Option<HashMap<String, Column>> {
+    // with Day or Month bucketing only the lookback window back from now is searched, older rows aren't found
+    pub fn first(&self, conn: &mut Session, group: i64, fc: &FieldConf, key: Column) -> Result<Option<HashMap<String, Column>>> {
 
-        match fc.qt {
-            QueryType::Storaged => {}
-            QueryType::Value | QueryType::Substring => {
+        select_first(conn, self.first_queries(group, fc, key))
+    }
 
-                let mut values = vec![];
+    // soft deleted rows read as None
+    pub fn first_by_id(&self, conn: &mut Session, group: i64, id: i64) -> Result<Option<HashMap<String, Column>>> {
 
-                values.push(Column::Bigint(group));
-                values.push(key);
+        let row = try!(self.first_by_id_with_deleted(conn, group, id));
 
-                let field_name = fc.f.get_name();
+        Ok(row.filter(|x| !is_deleted(x)))
+    }
 
-                let query = "select * from test1.".to_string() + self.name + "_by_field_" + field_name + " where group = ? and " + field_name + " = ? limit 1";
+    pub fn first_by_id_with_deleted(&self, conn: &mut Session, group: i64, id: i64) -> Result<Option<HashMap<String, Column>>> {
 
-                let result = conn.prm_query(query, values, Consistency::Quorum).unwrap();
+        let (query, values) = self.first_by_id_query(group, id);
 
-                //println!("result of first is {:?}", result);
+        let result = try!(conn.read(query, values, Consistency::Quorum));
 
-                match result {
-                    Response::Result(rb) => {
-                        match rb {
-                            ResultBody::Rows(rows, paging_state) => {
-                                if rows.len() > 0 {
-                                    return Some(rows[0].columns.clone())
-                                }
-                            }
-                            _ => {}
-                        }
-                    }
-                    _ => {}
-                }
-            }
+        //println!("result of first is {:?}", result);
+
+        Ok(first_row(result))
+    }
+
+    pub fn first_as<R: FromRow>(&self, conn: &mut Session, group: i64, fc: &FieldConf, key: Column) -> result::Result<Option<R>, Error> {
+        match try!(self.first(conn, group, fc, key)) {
+            Some(row) => self.decode(&row).map(Some),
+            None => Ok(None)
         }
+    }
 
-        None
+    pub fn first_by_id_as<R: FromRow>(&self, conn: &mut Session, group: i64, id: i64) -> result::Result<Option<R>, Error> {
+        match try!(self.first_by_id(conn, group, id)) {
+            Some(row) => self.decode(&row).map(Some),
+            None => Ok(None)
+        }
     }
 
-    pub fn first_by_id(&self, conn: &mut Connection, group: i64, id: i64) -> Option<HashMap<String, Column>> {
+    pub fn decode<R: FromRow>(&self, row: &HashMap<String, Column>) -> result::Result<R, Error> {
+        match self.fields {
+            Some(ref fields) => R::from_row(row, fields),
+            None => R::from_row(row, &[])
+        }
+    }
 
-        let mut values = vec![];
+    // only Value and Substring fields have a table to look them up in, one query per bucket
+    pub fn first_queries(&self, group: i64, fc: &FieldConf, key: Column) -> Vec<(String, Vec<Column>)> {
 
-        values.push(Column::Bigint(group));
-        values.push(Column::Bigint(id));
+        match fc.qt {
+            QueryType::Storaged => vec![],
+            QueryType::Value | QueryType::Substring => {
 
-        let query = "select * from test1.".to_string() + self.name + " where group = ? and id = ? limit 1";
+                let field_name = fc.f.get_name();
+
+                let query = "select * from test1.".to_string() + self.name + "_by_field_" + field_name + " where " + self.partition_where() + " and " + field_name + " = " + placeholder(&fc.f) + " limit 1";
 
-        let result = conn.prm_query(query, values, Consistency::Quorum).unwrap();
+                self.read_partitions(group, self.buckets_from(None)).into_iter().map(|mut values| {
+                    values.push(key.clone());
+
+                    (query.clone(), values)
+                }).collect()
+            }
+        }
+    }
+
+    pub fn first_by_id_query(&self, group: i64, id: i64) -> (String, Vec<Column>) {
+
+        let query = "select * from test1.".to_string() + self.name + " where " + self.partition_where() + " and id = ? limit 1";
+
+        (query, self.key_values(group, id))
+    }
+}
+
+// first row of the first query that has one
+pub fn select_first(conn: &mut Session, queries: Vec<(String, Vec<Column>)>) -> Result<Option<HashMap<String, Column>>> {
+    for (query, values) in queries.into_iter() {
+        let result = try!(conn.read(query, values, Consistency::Quorum));
 
         //println!("result of first is {:?}", result);
 
-        match result {
-            Response::Result(rb) => {
-                match rb {
-                    ResultBody::Rows(rows, paging_state) => {
-                        if rows.len() > 0 {
-                            return Some(rows[0].columns.clone())
-                        }
+        if let Some(row) = first_row(result) {
+            return Ok(Some(row))
+        }
+    }
+
+    Ok(None)
+}
+
+pub fn first_row(result: Response) -> Option<HashMap<String, Column>> {
+    match result {
+        Response::Result(rb) => {
+            match rb {
+                ResultBody::Rows(rows, paging_state) => {
+                    if rows.len() > 0 {
+                        return Some(rows[0].columns.clone())
                     }
-                    _ => {}
                 }
+                _ => {}
             }
-            _ => {}
         }
-
-        None
+        _ => {}
     }
-}
\ No newline at end of file
+
+    None
+}
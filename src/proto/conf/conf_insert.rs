@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 use std::io::Result;
+use std::result;
 
 use chrono::*;
 
@@ -20,6 +21,12 @@ use proto::jobs::jobs_delete::*;
 
 use proto::conf::*;
 use proto::schema::Schema;
+use proto::session::Session;
+use proto::error::Error;
+use proto::id::IdGenerator;
+use proto::conf::conf_count::overlay;
+use proto::udt::bind_values;
+use proto::batch::CounterUpdate;
 
 
 // (group id) f1 f2 ... fn                                  main
@@ -29,12 +36,35 @@ use proto::schema::Schema;
 // (group entity_id id row) f1 f2 ... fn                    by_many
 
 impl<'a, T> Conf<'a, T> {
-    pub fn insert_hashmap(&self, mut conn: &mut Connection, group: i64, id: i64, hashmap: HashMap<String, Column>, extra_fields: Option<Vec<(&FieldConf, Column)>>, consistency: Consistency ) -> Result<Response> {
+    pub fn insert_hashmap(&self, mut conn: &mut Session, group: i64, id: i64, hashmap: HashMap<String, Column>, extra_fields: Option<Vec<(&FieldConf, Column)>>, consistency: Consistency, opts: &WriteOptions) -> Result<Response> {
+        try!(self.check_id(id));
 
         let mut f: Vec<&FieldConf> = vec![];
         let mut values: Vec<Column> = vec![];
 
+        self.hashmap_fields(hashmap, &mut f, &mut values);
 
+        if let Some(ef) = extra_fields {
+            for &(fc, ref v) in ef.iter() {
+                f.push(fc);
+                values.push(v.clone());
+            }
+        }
+
+        let mut values = try!(self.check_values(&f, values, opts));
+
+        let state = try!(self.first_by_id_with_deleted(&mut conn, group, id));
+
+        self.fill(&mut f, &mut values, &[], &state);
+
+        let state = self.revive(state, &mut f, &mut values, &[]);
+
+        let counters = self.counters_for(group, &state, &f, &values);
+
+        conn.execute_with_counters(self.get_batch_for_insert(group, id, f, values, state, opts), counters, consistency, &opts.batch, true)
+    }
+    // fields of the Conf found in hashmap, with their values
+    pub fn hashmap_fields<'b>(&'b self, hashmap: HashMap<String, Column>, f: &mut Vec<&'b FieldConf<'b>>, values: &mut Vec<Column>) {
         if let Some(ref field_confs) = self.fields {
 
             for (k, v) in hashmap.iter() {
@@ -45,34 +75,173 @@ impl<'a, T> Conf<'a, T> {
             }
 
         }
+    }
+    pub fn insert(&self, mut conn: &mut Session, group: i64, id: i64, f_v: Vec<(&FieldConf, Column)>, consistency: Consistency, opts: &WriteOptions) -> Result<Response> {
+        try!(self.check_id(id));
 
-        if let Some(ef) = extra_fields {
-            for &(fc, ref v) in ef.iter() {
-                f.push(fc);
-                values.push(v.clone());
-            }
+        let mut f = vec![];
+
+        let mut values = vec![];
+
+
+        for &(fc, ref v) in f_v.iter() {
+            f.push(fc);
+            values.push(v.clone());
         }
 
-        let state = self.first_by_id(&mut conn, group, id);
+        let mut values = try!(self.check_values(&f, values, opts));
+
+        let state = try!(self.first_by_id_with_deleted(&mut conn, group, id));
+
+        self.fill(&mut f, &mut values, &[], &state);
+
+        let state = self.revive(state, &mut f, &mut values, &[]);
 
-        conn.execute_batch(self.get_batch_for_insert(group, id, f, values, state), consistency)
+        let counters = self.counters_for(group, &state, &f, &values);
+
+        conn.execute_with_counters(self.get_batch_for_insert(group, id, f, values, state, opts), counters, consistency, &opts.batch, true)
     }
-    pub fn insert(&self, mut conn: &mut Connection, group: i64, id: i64, f_v: Vec<(&FieldConf, Column)>, consistency: Consistency) -> Result<Response> {
-        let state = self.first_by_id(&mut conn, group, id);
+    // Set writes the value, Null clears it and drops its index rows, Unchanged fields are left out
+    pub fn update(&self, mut conn: &mut Session, group: i64, id: i64, f_v: Vec<(&FieldConf, FieldValue)>, consistency: Consistency, opts: &WriteOptions) -> Result<Response> {
+        try!(self.check_id(id));
+
+        let (f, values, nulls) = try!(self.update_values(f_v, opts));
+
+        let state = try!(self.first_by_id_with_deleted(&mut conn, group, id));
 
+        let (batch, counters) = self.update_jobs(group, id, f, values, nulls, state, opts);
+
+        conn.execute_with_counters(batch, counters, consistency, &opts.batch, true)
+    }
+    // the fields to set with their checked values and the fields to null
+    pub fn update_values<'b>(&self, f_v: Vec<(&'b FieldConf<'b>, FieldValue)>, opts: &WriteOptions) -> result::Result<(Vec<&'b FieldConf<'b>>, Vec<Column>, Vec<&'b FieldConf<'b>>), Error> {
         let mut f = vec![];
 
         let mut values = vec![];
 
+        let mut nulls = vec![];
 
         for &(fc, ref v) in f_v.iter() {
-            f.push(fc);
-            values.push(v.clone());
+            match v {
+                &FieldValue::Set(ref c) => {
+                    f.push(fc);
+                    values.push(c.clone());
+                }
+                &FieldValue::Null => {
+                    if !fc.nullable {
+                        return Err(Error::NotNullable(fc.f.get_name().to_string()));
+                    }
+                    nulls.push(fc);
+                }
+                &FieldValue::Unchanged => {}
+            }
         }
 
-        conn.execute_batch(self.get_batch_for_insert(group, id, f, values, state), consistency)
+        let values = try!(self.check_values(&f, values, opts));
+
+        Ok((f, values, nulls))
     }
-    pub fn get_batch_for_insert(&self, group: i64, id: i64, f: Vec<&FieldConf>, mut values: Vec<Column>, state: Option<HashMap<String, Column>>) -> Vec<BatchQuery> {
+    // state is the row as stored, soft deleted or not
+    pub fn update_jobs<'b>(&'b self, group: i64, id: i64, mut f: Vec<&'b FieldConf<'b>>, mut values: Vec<Column>, nulls: Vec<&'b FieldConf<'b>>, state: Option<HashMap<String, Column>>, opts: &WriteOptions) -> (Vec<BatchQuery>, Vec<CounterUpdate>) {
+        self.fill(&mut f, &mut values, &nulls, &state);
+
+        // an explicit null wins over a default
+        while let Some(i) = f.iter().position(|x| nulls.iter().any(|n| n.f.get_name() == x.f.get_name())) {
+            f.remove(i);
+            values.remove(i);
+        }
+
+        let state = self.revive(state, &mut f, &mut values, &nulls);
+
+        let counters = if self.counters {
+            let mut row = overlay(&state, &f, &values);
+
+            for fc in nulls.iter() {
+                row.remove(fc.f.get_name());
+            }
+
+            self.count_jobs(group, state.as_ref(), Some(&row))
+        } else {
+            vec![]
+        };
+
+        (self.get_batch_for_update(group, id, f, values, nulls, state, opts), counters)
+    }
+    pub fn get_batch_for_update(&self, group: i64, id: i64, f: Vec<&FieldConf>, values: Vec<Column>, nulls: Vec<&FieldConf>, state: Option<HashMap<String, Column>>, opts: &WriteOptions) -> Vec<BatchQuery> {
+
+        let opts = self.get_write_options(opts);
+
+        let mut batch = vec![];
+
+        if let Some(ref state) = state {
+            for fc in nulls.iter() {
+                if let Some(c) = state.get(fc.f.get_name()) {
+                    // a nulled set loses all of its elements
+                    batch.extend(field_delete_job(self, fc, group, id, vec![], state.clone(), c.clone(), &Column::Set(vec![]), &opts));
+                }
+            }
+        }
+
+        if f.len() > 0 {
+            batch.extend(self.get_batch_for_insert(group, id, f.clone(), values.clone(), state.clone(), &opts));
+        }
+
+        if nulls.len() == 0 {
+            return batch;
+        }
+
+        // nulls are literals, there is no column to bind for them
+        let set = nulls.iter().map(|x| x.f.get_name().to_string() + " = null").collect::<Vec<String>>().join(", ");
+
+        let query = "update test1.".to_string() + self.name + &opts.using() + " set " + &set + " where " + self.partition_where() + " and id = ?";
+
+        batch.push(BatchQuery::SimpleWithParams(query, opts.using_then_values(self.key_values(group, id))));
+
+        // every index row carries a copy of the row, the nulls go there too,
+        // fields of the update key their rows by the new value, the others by state
+        if let Some(ref fields) = self.fields {
+            for x in fields.iter() {
+                if let QueryType::Storaged = x.qt {
+                    continue;
+                }
+
+                let field_name = x.f.get_name();
+
+                if nulls.iter().any(|n| n.f.get_name() == field_name) {
+                    continue;
+                }
+
+                let key = match f.iter().position(|y| y.f.get_name() == field_name) {
+                    Some(i) => values[i].clone(),
+                    None => match state.as_ref().and_then(|s| s.get(field_name)) {
+                        Some(c) => c.clone(),
+                        None => continue
+                    }
+                };
+
+                let query = "update test1.".to_string() + self.name + "_by_field_" + field_name + &opts.using() + " set " + &set + " where " + self.partition_where() + " and " + field_name + " = " + placeholder(&x.f) + " and id = ?";
+
+                let keys = match x.f {
+                    Field::Set(_, _) => elements(&key),
+                    _ => vec![key]
+                };
+
+                for key in keys.into_iter() {
+                    let mut values = self.partition_values(group, id);
+
+                    values.push(key);
+                    values.push(Column::Bigint(id));
+
+                    batch.push(BatchQuery::SimpleWithParams(query.clone(), opts.using_then_values(values)));
+                }
+            }
+        }
+
+        batch
+    }
+    pub fn get_batch_for_insert(&self, group: i64, id: i64, f: Vec<&FieldConf>, mut values: Vec<Column>, state: Option<HashMap<String, Column>>, opts: &WriteOptions) -> Vec<BatchQuery> {
+
+        let opts = self.get_write_options(opts);
 
         let mut batch = vec![];
 
@@ -82,10 +251,10 @@ impl<'a, T> Conf<'a, T> {
             }
 
             for i in 0..f.len() {
-                let c = state.get(f[i].f.get_name()).unwrap();
-                if *c != values[i] {
-                    if let Some(bq) = field_delete_job(self, &f[i], group, id, values.clone(), state.clone(), c.clone()) {
-                        batch.push(bq);
+                // a null in state has no index row to delete
+                if let Some(c) = state.get(f[i].f.get_name()) {
+                    if *c != values[i] {
+                        batch.extend(field_delete_job(self, &f[i], group, id, values.clone(), state.clone(), c.clone(), &values[i], &opts));
                     }
                 }
             }
@@ -94,16 +263,14 @@ impl<'a, T> Conf<'a, T> {
 
         let name = self.name;
 
-        let mut query = "insert into test1.".to_string() + name + " (group,id,";
+        let mut query = "insert into test1.".to_string() + name + " (" + self.partition_columns() + "id," + self.deleted_insert_column();
 
         if f.len() != values.len() {
             panic!("fields and values count don't match");
         }
 
         query = f.iter().fold(query, |query, x| {
-            if let Some(bq) = field_insert_job(self, x, &f, group, id, values.clone()) {
-                batch.push(bq);
-            }
+            batch.extend(field_insert_job(self, x, &f, group, id, values.clone(), &opts));
             query + &x.f.get_name() + ","
         });
 
@@ -111,22 +278,19 @@ impl<'a, T> Conf<'a, T> {
 
         query.truncate(len - 1);
 
-        query = query + ") values (?,?,";
+        query = query + ") values (" + self.partition_placeholders() + "?," + self.deleted_insert_value();
 
-        for i in 0..values.len() {
-            query = query + "?,";
-        }
+        query = f.iter().fold(query, |query, x| add_placeholder(query, &x.f));
 
         let len = query.len();
 
         query.truncate(len - 1);
 
-        query = query + ")";
+        query = query + ")" + &opts.using();
 
         //println!("{}", query);
 
-        values.insert(0, Column::Bigint(id));
-        values.insert(0, Column::Bigint(group));
+        let values = opts.values_then_using(self.keyed(group, id, bind_values(&f, values)));
 
         //println!("{:?}", values);
 
@@ -134,11 +298,35 @@ impl<'a, T> Conf<'a, T> {
 
         batch
     }
-    pub fn insert_all(&self, mut conn: &mut Connection, group: i64, id: i64, mut values: Vec<Column>, consistency: Consistency) -> Result<Response> {
-        let state = self.first_by_id(&mut conn, group, id);
-        conn.execute_batch(self.get_batch_for_insert_all(group, id, values, state), consistency)
+    pub fn insert_all(&self, mut conn: &mut Session, group: i64, id: i64, mut values: Vec<Column>, consistency: Consistency, opts: &WriteOptions) -> Result<Response> {
+        try!(self.check_id(id));
+
+        self.fill_all(&mut values);
+
+        let mut values = try!(self.check_all_values(values, opts));
+        let state = try!(self.first_by_id_with_deleted(&mut conn, group, id));
+        let state = self.revive_all(state, &mut values);
+        let counters = self.counters_for_all(group, &state, &values);
+
+        conn.execute_with_counters(self.get_batch_for_insert_all(group, id, values, state, opts), counters, consistency, &opts.batch, true)
     }
-    pub fn get_batch_for_insert_all(&self, group: i64, id: i64, mut values: Vec<Column>, state: Option<HashMap<String, Column>>) -> Vec<BatchQuery> {
+    // the id is new, there is no state to read
+    pub fn insert_new(&self, conn: &mut Session, ids: &mut IdGenerator, group: i64, mut values: Vec<Column>, consistency: Consistency, opts: &WriteOptions) -> Result<i64> {
+        self.fill_all(&mut values);
+
+        let values = try!(self.check_all_values(values, opts));
+
+        let id = ids.next_id(group);
+
+        let counters = self.counters_for_all(group, &None, &values);
+
+        try!(conn.execute_with_counters(self.get_batch_for_insert_all(group, id, values, None, opts), counters, consistency, &opts.batch, true));
+
+        Ok(id)
+    }
+    pub fn get_batch_for_insert_all(&self, group: i64, id: i64, mut values: Vec<Column>, state: Option<HashMap<String, Column>>, opts: &WriteOptions) -> Vec<BatchQuery> {
+
+        let opts = self.get_write_options(opts);
 
         let mut batch = vec![];
 
@@ -150,10 +338,9 @@ impl<'a, T> Conf<'a, T> {
                 }
 
                 for i in 0..f.len() {
-                    let c = state.get(f[i].f.get_name()).unwrap();
-                    if *c != values[i] {
-                        if let Some(bq) = field_delete_job(self, &f[i], group, id, values.clone(), state.clone(), c.clone()) {
-                            batch.push(bq);
+                    if let Some(c) = state.get(f[i].f.get_name()) {
+                        if *c != values[i] {
+                            batch.extend(field_delete_job(self, &f[i], group, id, values.clone(), state.clone(), c.clone(), &values[i], &opts));
                         }
                     }
                 }
@@ -161,7 +348,7 @@ impl<'a, T> Conf<'a, T> {
         }
 
         let name = self.name;
-        let mut query = "insert into test1.".to_string() + name + " (group,id,";
+        let mut query = "insert into test1.".to_string() + name + " (" + self.partition_columns() + "id," + self.deleted_insert_column();
 
         if let Some(ref f) = self.fields {
 
@@ -170,9 +357,7 @@ impl<'a, T> Conf<'a, T> {
             }
 
             query = f.iter().fold(query, |query, x| {
-                if let Some(bq) = field_insert_all_job(self, x, group, id, values.clone()) {
-                    batch.push(bq);
-                }
+                batch.extend(field_insert_all_job(self, x, group, id, values.clone(), &opts));
                 query + &x.f.get_name() + ","
             });
         }
@@ -181,22 +366,26 @@ impl<'a, T> Conf<'a, T> {
 
         query.truncate(len - 1);
 
-        query = query + ") values (?,?,";
+        query = query + ") values (" + self.partition_placeholders() + "?," + self.deleted_insert_value();
 
-        for i in 0..values.len() {
-            query = query + "?,";
+        if let Some(ref f) = self.fields {
+            query = f.iter().fold(query, |query, x| add_placeholder(query, &x.f));
         }
 
         let len = query.len();
 
         query.truncate(len - 1);
 
-        query = query + ")";
+        query = query + ")" + &opts.using();
 
         //println!("{}", query);
 
-        values.insert(0, Column::Bigint(id));
-        values.insert(0, Column::Bigint(group));
+        let values = match self.fields {
+            Some(ref f) => bind_values(&f.iter().collect::<Vec<_>>(), values),
+            None => values
+        };
+
+        let values = opts.values_then_using(self.keyed(group, id, values));
 
         //println!("{:?}", values);
 
@@ -204,4 +393,14 @@ impl<'a, T> Conf<'a, T> {
 
         batch
     }
+    // the ttl of the Conf applies unless the write sets its own,
+    // without a timestamp the whole batch is stamped with the current time so index rows and the main row agree
+    pub fn get_write_options(&self, opts: &WriteOptions) -> WriteOptions {
+        WriteOptions {
+            ttl: opts.ttl.or(self.ttl),
+            timestamp: Some(opts.timestamp.unwrap_or_else(next_micros)),
+            batch: opts.batch,
+            coerce: opts.coerce
+        }
+    }
 }
\ No newline at end of file
@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 use std::io::Result;
+use std::result;
 
 use chrono::*;
 
@@ -20,6 +21,11 @@ use proto::jobs::jobs_delete::*;
 
 use proto::conf::*;
 use proto::schema::Schema;
+use proto::session::Session;
+use proto::row::FromRow;
+use proto::error::Error;
+use proto::id::id_at_time;
+use proto::conf::conf_delete::is_deleted;
 
 
 // (group id) f1 f2 ... fn                                  main
@@ -29,78 +35,212 @@ use proto::schema::Schema;
 // (group entity_id id row) f1 f2 ... fn                    by_many
 
 impl<'a, T> Conf<'a, T> {
-    pub fn list_with_limit_by_id(conn: &mut Connection, group: i64, last_item_id: Option<i64>) -> Vec<HashMap<String, Column>> {
-        let mut values = vec![];
-        values.push(Column::Bigint(group));
+    // soft deleted rows are skipped, the scan goes on past them to fill the page
+    pub fn list_with_limit_by_id(&self, conn: &mut Session, group: i64, last_item_id: Option<i64>) -> Result<Vec<HashMap<String, Column>>> {
+        select_live(conn, last_item_id, &|last| Ok(self.list_with_limit_by_id_queries(group, last)))
+    }
 
-        let mut query: String;
+    pub fn list_with_limit_by_id_with_deleted(&self, conn: &mut Session, group: i64, last_item_id: Option<i64>) -> Result<Vec<HashMap<String, Column>>> {
+        select_all(conn, self.list_with_limit_by_id_queries(group, last_item_id))
+    }
 
-        match last_item_id {
-            Some(id) => {
-                values.push(Column::Bigint(id));
-                query = "select * from test1.company where group = ? and id < ? limit 10".to_string();
-            }
-            None => {
-                query = "select * from test1.company where group = ? limit 10".to_string();
+    // pages through a _by_field_ table in its clustering order, last is the field value and id of the last row seen,
+    // soft deleted rows have no index rows to find
+    pub fn list_by_field(&self, conn: &mut Session, group: i64, fc: &FieldConf, last: Option<(Column, i64)>) -> Result<Vec<HashMap<String, Column>>> {
+        select_all(conn, self.list_by_field_queries(group, fc, last))
+    }
+
+    pub fn list_with_limit_by_id_as<R: FromRow>(&self, conn: &mut Session, group: i64, last_item_id: Option<i64>) -> result::Result<Vec<R>, Error> {
+        let rows = try!(self.list_with_limit_by_id(conn, group, last_item_id));
+
+        rows.iter().map(|row| self.decode(row)).collect()
+    }
+
+    pub fn list_by_field_as<R: FromRow>(&self, conn: &mut Session, group: i64, fc: &FieldConf, last: Option<(Column, i64)>) -> result::Result<Vec<R>, Error> {
+        let rows = try!(self.list_by_field(conn, group, fc, last));
+
+        rows.iter().map(|row| self.decode(row)).collect()
+    }
+
+    // rows created in [from, to), newest first, ids must be simpleflake ids
+    pub fn list_by_time(&self, conn: &mut Session, group: i64, from: DateTime<UTC>, to: DateTime<UTC>, last_item_id: Option<i64>) -> Result<Vec<HashMap<String, Column>>> {
+        select_live(conn, last_item_id, &|last| self.list_by_time_queries(group, &from, &to, last))
+    }
+
+    pub fn list_by_time_with_deleted(&self, conn: &mut Session, group: i64, from: DateTime<UTC>, to: DateTime<UTC>, last_item_id: Option<i64>) -> Result<Vec<HashMap<String, Column>>> {
+        select_all(conn, try!(self.list_by_time_queries(group, &from, &to, last_item_id)))
+    }
+
+    pub fn list_by_time_as<R: FromRow>(&self, conn: &mut Session, group: i64, from: DateTime<UTC>, to: DateTime<UTC>, last_item_id: Option<i64>) -> result::Result<Vec<R>, Error> {
+        let rows = try!(self.list_by_time(conn, group, from, to, last_item_id));
+
+        rows.iter().map(|row| self.decode(row)).collect()
+    }
+
+    // with bucketing the queries go through the buckets, only the first one continues after the last row seen
+    pub fn list_by_time_queries(&self, group: i64, from: &DateTime<UTC>, to: &DateTime<UTC>, last_item_id: Option<i64>) -> Result<Vec<(String, Vec<Column>)>> {
+        let low = id_at_time(from);
+        let high = id_at_time(to);
+
+        let mut upper = high;
+
+        // the next page starts below the last id seen
+        if let Some(id) = last_item_id {
+            if id < upper {
+                upper = id;
             }
         }
 
-        //println!("query is {}", query);
+        let buckets = match self.bucketing {
+            Some(ref b) => try!(b.buckets_between(low, high, last_item_id)),
+            None => vec![]
+        };
+
+        let query = "select * from test1.".to_string() + self.name + " where " + self.partition_where() + " and id >= ? and id < ? limit 10";
+
+        Ok(self.read_partitions(group, buckets).into_iter().enumerate().map(|(i, mut values)| {
+            values.push(Column::Bigint(low));
+            values.push(Column::Bigint(if i == 0 { upper } else { high }));
+
+            (query.clone(), values)
+        }).collect())
+    }
+
+    pub fn list_with_limit_by_id_queries(&self, group: i64, last_item_id: Option<i64>) -> Vec<(String, Vec<Column>)> {
+        let partitions = self.read_partitions(group, self.buckets_from(last_item_id));
+
+        partitions.into_iter().enumerate().map(|(i, mut values)| {
+            match last_item_id {
+                Some(id) if i == 0 => {
+                    values.push(Column::Bigint(id));
 
-        let result = conn.prm_query(query, values, Consistency::Quorum).unwrap();
+                    ("select * from test1.".to_string() + self.name + " where " + self.partition_where() + " and id < ? limit 10", values)
+                }
+                _ => ("select * from test1.".to_string() + self.name + " where " + self.partition_where() + " limit 10", values)
+            }
+        }).collect()
+    }
 
-        //println!("result of proto list is {:?}", result);
+    // queries are run in order until 10 rows are there
+    pub fn list_by_field_queries(&self, group: i64, fc: &FieldConf, last: Option<(Column, i64)>) -> Vec<(String, Vec<Column>)> {
+        let field_name = fc.f.get_name();
+
+        let table = "test1.".to_string() + self.name + "_by_field_" + field_name;
+
+        let partitions = self.read_partitions(group, self.buckets_from(last.as_ref().map(|&(_, id)| id)));
 
         let mut res = vec![];
 
-        match result {
-            Response::Result(rb) => {
-                match rb {
-                    ResultBody::Rows(rows, paging_state) => {
-                        for row in rows.iter() {
-                            res.push(row.columns.clone());
-                        }
-                    }
-                    _ => {}
+        for (i, values) in partitions.into_iter().enumerate() {
+            match last {
+                Some((ref value, id)) if i == 0 => {
+                    // rest of the rows with the same value, ids are always desc
+                    let query = "select * from ".to_string() + &table + " where " + self.partition_where() + " and " + field_name + " = " + placeholder(&fc.f) + " and id < ? limit 10";
+
+                    let op = if fc.is_desc() { " < " } else { " > " };
+
+                    let next = "select * from ".to_string() + &table + " where " + self.partition_where() + " and " + field_name + op + placeholder(&fc.f) + " limit 10";
+
+                    let mut same = values.clone();
+                    same.push(value.clone());
+                    same.push(Column::Bigint(id));
+
+                    let mut rest = values;
+                    rest.push(value.clone());
+
+                    res.push((query, same));
+                    res.push((next, rest));
+                }
+                _ => {
+                    let query = "select * from ".to_string() + &table + " where " + self.partition_where() + " limit 10";
+
+                    res.push((query, values));
                 }
             }
-            _ => {}
         }
 
         res
     }
 }
 
-// (group id) f1 f2 ... fn                                  main
-// (group f1 id) f2 ... fn                                  Value
+pub fn select_all(conn: &mut Session, queries: Vec<(String, Vec<Column>)>) -> Result<Vec<HashMap<String, Column>>> {
+    let mut res = vec![];
+
+    for (query, values) in queries.into_iter() {
+        if res.len() >= 10 {
+            break;
+        }
 
+        res.extend(try!(select(conn, query, values)));
+    }
 
-/*
-pub fn prepare(conn: &mut Connection) ->Result<Vec<u8>, ()> {
-    let result = conn.prepare("
-		insert into test1.person_first_name_index (group, first_name_index, first_name) values (?, ?, ?)
-		".to_string());
-
-    if let Response::Result(result) = result.unwrap() {
-        if let ResultBody::Prepared(id) = result {
-            return Ok(id)
+    res.truncate(10);
+
+    Ok(res)
+}
+
+// pages of the main table without soft deleted rows, queries gives the queries of a page after the last id
+pub fn select_live(conn: &mut Session, last_item_id: Option<i64>, queries: &Fn(Option<i64>) -> Result<Vec<(String, Vec<Column>)>>) -> Result<Vec<HashMap<String, Column>>> {
+    let mut res = vec![];
+    let mut last = last_item_id;
+
+    loop {
+        let rows = try!(select_all(conn, try!(queries(last))));
+
+        match keep_live(&mut res, rows) {
+            Some(id) => last = Some(id),
+            None => break
         }
     }
 
-    Err(())
+    res.truncate(10);
+
+    Ok(res)
+}
+
+// keeps the rows that aren't soft deleted, the id to scan on from while a full page came back and res isn't full yet
+pub fn keep_live(res: &mut Vec<HashMap<String, Column>>, rows: Vec<HashMap<String, Column>>) -> Option<i64> {
+    let full = rows.len() >= 10;
+
+    let last = match rows.last().and_then(|x| x.get("id")) {
+        Some(&Column::Bigint(id)) => Some(id),
+        _ => None
+    };
+
+    res.extend(rows.into_iter().filter(|x| !is_deleted(x)));
+
+    if full && res.len() < 10 { last } else { None }
 }
 
-pub fn add(prepared_id: Vec<u8>, group: i64, index: String, value: String) -> BatchQuery {
-    let mut values = vec![];
+pub fn select(conn: &mut Session, query: String, values: Vec<Column>) -> Result<Vec<HashMap<String, Column>>> {
+
+    //println!("query is {}", query);
+
+    let result = try!(conn.read(query, values, Consistency::Quorum));
 
-    values.push(Column::Bigint(group));
-    values.push(Column::String(index));
-    values.push(Column::String(value));
+    //println!("result of proto list is {:?}", result);
+
+    let mut res = vec![];
 
-    BatchQuery::Prepared(prepared_id, values)
+    match result {
+        Response::Result(rb) => {
+            match rb {
+                ResultBody::Rows(rows, paging_state) => {
+                    for row in rows.iter() {
+                        res.push(row.columns.clone());
+                    }
+                }
+                _ => {}
+            }
+        }
+        _ => {}
+    }
+
+    Ok(res)
 }
 
-*/
+// (group id) f1 f2 ... fn                                  main
+// (group f1 id) f2 ... fn                                  Value
 
 
 /*
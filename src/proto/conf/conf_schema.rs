@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 use std::io::Result;
+use std::result;
 
 use chrono::*;
 
@@ -20,6 +21,7 @@ use proto::jobs::jobs_delete::*;
 
 use proto::conf::*;
 use proto::schema::Schema;
+use proto::error::Error;
 
 
 // (group id) f1 f2 ... fn                                  main
@@ -29,14 +31,15 @@ use proto::schema::Schema;
 // (group entity_id id row) f1 f2 ... fn                    by_many
 
 impl<'a, T> Conf<'a, T> {
-    pub fn get_schema(&self) -> Schema {
+    // fails only on user-defined types that depend on each other in a cycle
+    pub fn get_schema(&self) -> result::Result<Schema, Error> {
 
         let mut s = Schema {
             queries: vec![]
         };
 
-        self.create(&mut s);
+        try!(self.create(&mut s));
 
-        s
+        Ok(s)
     }
 }
\ No newline at end of file
@@ -3,6 +3,10 @@ pub mod conf_create;
 pub mod conf_insert;
 pub mod conf_first;
 pub mod conf_list;
+pub mod conf_collection;
+pub mod conf_fill;
+pub mod conf_count;
+pub mod conf_delete;
 
 use std::collections::HashMap;
 use std::io::Result;
@@ -25,6 +29,9 @@ use proto::jobs::jobs_insert_all::*;
 use proto::jobs::jobs_delete::*;
 
 use proto::schema::Schema;
+use proto::udt::Udt;
+use proto::options::*;
+use proto::bucket::Bucketing;
 
 
 
@@ -41,7 +48,16 @@ pub struct Conf<'a, T> {
     pub e: T,
     pub fields: Option<Vec<FieldConf<'a>>>,
     pub by_entity: Option<Vec<&'a str>>,
-    pub by_many: Option<Vec<&'a str>>
+    pub by_many: Option<Vec<&'a str>>,
+    pub types: Option<Vec<Udt<'a>>>,
+    pub options: TableOptions,
+    pub overrides: Option<Vec<(&'a str, TableOptions)>>,
+    pub ttl: Option<u32>,
+    pub bucketing: Option<Bucketing>,
+    // maintain counter tables of rows per group and per Value, see conf_count
+    pub counters: bool,
+    // delete stamps deleted_at and keeps the main row, see conf_delete
+    pub soft_delete: bool
 }
 
 pub fn new_conf<'a, E: 'a>(name: &'a str, e: E, fields: Option<Vec<FieldConf<'a>>>, by_entity: Option<Vec<&str>>, by_many: Option<Vec<&str>>) -> Conf<'a, E> {
@@ -50,6 +66,13 @@ pub fn new_conf<'a, E: 'a>(name: &'a str, e: E, fields: Option<Vec<FieldConf<'a>
         name: name,
         fields: fields,
         by_entity: None,
-        by_many: None
+        by_many: None,
+        types: None,
+        options: new_table_options(),
+        overrides: None,
+        ttl: None,
+        bucketing: None,
+        counters: false,
+        soft_delete: false
     }
 }
\ No newline at end of file
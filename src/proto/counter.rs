@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::io::Result;
+use std::io::Error as IoError;
+use std::io::ErrorKind;
+
+use rustcql::shared::Consistency;
+use rustcql::shared::Response;
+use rustcql::shared::Column;
+
+use proto::schema::Schema;
+use proto::session::Session;
+use proto::batch::CounterUpdate;
+use proto::options::*;
+use proto::error::Error;
+use proto::conf::conf_first::select_first;
+
+
+// (group id) c1 c2 ... cn                                  counters
+
+// an entity made only of counter columns, e.g. views and likes per id,
+// its updates are CounterUpdates so they never go into the batches of a Conf
+
+pub struct CounterConf<'a> {
+    pub name: &'a str,
+    pub counters: Vec<&'a str>,
+    pub options: TableOptions
+}
+
+pub fn new_counter_conf<'a>(name: &'a str, counters: Vec<&'a str>) -> CounterConf<'a> {
+    CounterConf {
+        name: name,
+        counters: counters,
+        options: new_table_options()
+    }
+}
+
+impl<'a> CounterConf<'a> {
+    pub fn get_schema(&self) -> Schema {
+
+        let mut s = Schema {
+            queries: vec![]
+        };
+
+        self.create(&mut s);
+
+        s
+    }
+    pub fn create(&self, schema: &mut Schema) {
+        let mut query = "create table ".to_string() + self.name + " (group bigint, id bigint,";
+
+        query = self.counters.iter().fold(query, |query, x| query + " " + x + " counter,");
+
+        query = query + " primary key (group, id)) with clustering order by (id desc) and " + &self.options.render_counter();
+
+        schema.queries.push(query);
+    }
+    pub fn increment(&self, conn: &mut Session, group: i64, id: i64, counter: &str, by: i64, consistency: Consistency) -> Result<Response> {
+        self.add(conn, group, id, vec![(counter, by)], consistency)
+    }
+    pub fn decrement(&self, conn: &mut Session, group: i64, id: i64, counter: &str, by: i64, consistency: Consistency) -> Result<Response> {
+        let by = try!(negate(by));
+
+        self.add(conn, group, id, vec![(counter, by)], consistency)
+    }
+    // several counters of the same id in one statement, negative deltas decrement
+    pub fn add(&self, conn: &mut Session, group: i64, id: i64, deltas: Vec<(&str, i64)>, consistency: Consistency) -> Result<Response> {
+        let update = try!(self.get_update(group, id, deltas));
+
+        conn.execute_counters(vec![update], consistency)
+    }
+    pub fn get_update(&self, group: i64, id: i64, deltas: Vec<(&str, i64)>) -> Result<CounterUpdate> {
+        if deltas.len() == 0 {
+            return Err(Error::Missing("counter".to_string()).into());
+        }
+
+        let mut set = vec![];
+        let mut values = vec![];
+
+        for &(counter, by) in deltas.iter() {
+            if !self.counters.contains(&counter) {
+                return Err(Error::Unknown(counter.to_string()).into());
+            }
+
+            if by < 0 {
+                set.push(counter.to_string() + " = " + counter + " - ?");
+                values.push(Column::Bigint(try!(negate(by))));
+            } else {
+                set.push(counter.to_string() + " = " + counter + " + ?");
+                values.push(Column::Bigint(by));
+            }
+        }
+
+        values.push(Column::Bigint(group));
+        values.push(Column::Bigint(id));
+
+        let query = "update test1.".to_string() + self.name + " set " + &set.join(", ") + " where group = ? and id = ?";
+
+        Ok(CounterUpdate { query: query, values: values })
+    }
+    // None when no counter of the id was ever updated, counters never updated read as 0
+    pub fn read(&self, conn: &mut Session, group: i64, id: i64) -> Result<Option<HashMap<String, i64>>> {
+        let row = try!(select_first(conn, vec![self.read_query(group, id)]));
+
+        Ok(row.map(|row| self.counters.iter().map(|x| {
+            let v = match row.get(*x) {
+                Some(&Column::Bigint(v)) => v,
+                _ => 0
+            };
+
+            (x.to_string(), v)
+        }).collect()))
+    }
+    pub fn read_counter(&self, conn: &mut Session, group: i64, id: i64, counter: &str) -> Result<i64> {
+        if !self.counters.contains(&counter) {
+            return Err(Error::Unknown(counter.to_string()).into());
+        }
+
+        let row = try!(self.read(conn, group, id));
+
+        Ok(row.and_then(|row| row.get(counter).cloned()).unwrap_or(0))
+    }
+    pub fn read_query(&self, group: i64, id: i64) -> (String, Vec<Column>) {
+        let query = "select * from test1.".to_string() + self.name + " where group = ? and id = ?";
+
+        (query, vec![Column::Bigint(group), Column::Bigint(id)])
+    }
+}
+
+// i64::MIN has no positive counterpart
+pub fn negate(by: i64) -> Result<i64> {
+    by.checked_neg().ok_or_else(|| IoError::new(ErrorKind::InvalidInput, format!("counter delta {} out of range", by)))
+}
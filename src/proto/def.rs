@@ -0,0 +1,320 @@
+use std::io::Result;
+use std::io::Error;
+use std::io::ErrorKind;
+
+use proto::shared::*;
+use proto::conf::*;
+use proto::udt::*;
+use proto::bucket::Bucketing;
+
+#[cfg(feature = "json")]
+use serde_json;
+#[cfg(feature = "config")]
+use serde_yaml;
+#[cfg(feature = "config")]
+use toml;
+
+
+// owned Conf definitions, loaded at runtime, to_conf borrows a Conf out of them
+//
+// [[tables]]
+// name = "person"
+// by_entity = ["union"]
+//
+// [[tables.fields]]
+// name = "first_name"
+// type = "text"
+// query = "substring"
+//
+// [[tables.fields]]
+// name = "tags"
+// type = "set<text>"
+// query = "value"
+// order = "asc"
+//
+// types are text, bigint, timestamp, double, list<t>, set<t>, map<k,v> and frozen<udt> for types declared in types
+// table options, overrides and fills are only set on a Conf in code, a definition naming them is rejected
+
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "json", serde(deny_unknown_fields))]
+pub struct SchemaDef {
+    pub tables: Vec<ConfDef>
+}
+
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "json", serde(deny_unknown_fields))]
+pub struct ConfDef {
+    pub name: String,
+    #[cfg_attr(feature = "json", serde(default))]
+    pub fields: Vec<FieldDef>,
+    #[cfg_attr(feature = "json", serde(default))]
+    pub by_entity: Vec<String>,
+    #[cfg_attr(feature = "json", serde(default))]
+    pub by_many: Vec<String>,
+    #[cfg_attr(feature = "json", serde(default))]
+    pub types: Vec<UdtDef>,
+    #[cfg_attr(feature = "json", serde(default))]
+    pub ttl: Option<u32>,
+    // day:<lookback>, month:<lookback> or hash:<shards>, day and month need simpleflake ids
+    #[cfg_attr(feature = "json", serde(default))]
+    pub bucketing: Option<String>,
+    #[cfg_attr(feature = "json", serde(default))]
+    pub counters: bool,
+    #[cfg_attr(feature = "json", serde(default))]
+    pub soft_delete: bool
+}
+
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "json", serde(deny_unknown_fields))]
+pub struct FieldDef {
+    pub name: String,
+    #[cfg_attr(feature = "json", serde(rename = "type"))]
+    pub kind: String,
+    // storaged, value or substring, storaged when missing
+    #[cfg_attr(feature = "json", serde(default))]
+    pub query: Option<String>,
+    // asc or desc, the type decides when missing
+    #[cfg_attr(feature = "json", serde(default))]
+    pub order: Option<String>,
+    #[cfg_attr(feature = "json", serde(default))]
+    pub nullable: bool
+}
+
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "json", serde(deny_unknown_fields))]
+pub struct UdtDef {
+    pub name: String,
+    pub fields: Vec<FieldDef>
+}
+
+#[cfg(feature = "config")]
+pub fn schema_from_toml(s: &str) -> Result<SchemaDef> {
+    toml::from_str(s).map_err(|e| invalid(format!("{}", e)))
+}
+
+#[cfg(feature = "json")]
+pub fn schema_from_json(s: &str) -> Result<SchemaDef> {
+    serde_json::from_str(s).map_err(|e| invalid(format!("{}", e)))
+}
+
+#[cfg(feature = "json")]
+pub fn schema_to_json(def: &SchemaDef) -> Result<String> {
+    serde_json::to_string_pretty(def).map_err(|e| invalid(format!("{}", e)))
+}
+
+#[cfg(feature = "config")]
+pub fn schema_from_yaml(s: &str) -> Result<SchemaDef> {
+    serde_yaml::from_str(s).map_err(|e| invalid(format!("{}", e)))
+}
+
+impl SchemaDef {
+    pub fn validate(&self) -> Result<()> {
+        for t in self.tables.iter() {
+            try!(t.validate());
+        }
+
+        Ok(())
+    }
+    pub fn to_confs<'a>(&'a self) -> Result<Vec<Conf<'a, ()>>> {
+        let mut res = vec![];
+
+        for t in self.tables.iter() {
+            res.push(try!(t.to_conf()));
+        }
+
+        Ok(res)
+    }
+}
+
+impl ConfDef {
+    pub fn validate(&self) -> Result<()> {
+        self.to_conf().map(|_| ())
+    }
+    pub fn to_conf<'a>(&'a self) -> Result<Conf<'a, ()>> {
+        let mut fields = vec![];
+
+        for fd in self.fields.iter() {
+            // columns of the main and index tables
+            if ["group", "id", "created_at", "updated_at"].contains(&&fd.name[..]) || (fd.name == "bucket" && self.bucketing.is_some()) {
+                return Err(invalid(format!("{}.{}: group, id, created_at, updated_at and bucket are reserved", self.name, fd.name)))
+            }
+
+            if fd.name == "deleted_at" && self.soft_delete {
+                return Err(invalid(format!("{}.{}: deleted_at is reserved with soft_delete", self.name, fd.name)))
+            }
+
+            if fd.name == "count" && self.counters {
+                return Err(invalid(format!("{}.{}: count is reserved with counters", self.name, fd.name)))
+            }
+
+            if fields.iter().any(|x: &FieldConf| x.f.get_name() == fd.name) {
+                return Err(invalid(format!("{}.{}: field is declared twice", self.name, fd.name)))
+            }
+
+            let fc = try!(self.field_conf(fd));
+
+            // Rules from shared.rs
+            match (&fc.qt, &fc.f) {
+                (&QueryType::Substring, &Field::Text(_)) => {}
+                (&QueryType::Substring, _) => {
+                    return Err(invalid(format!("{}.{}: substring fields must be text", self.name, fd.name)))
+                }
+                (&QueryType::Value, &Field::List(_, _)) | (&QueryType::Value, &Field::Map(_, _, _)) => {
+                    return Err(invalid(format!("{}.{}: lists and maps can't be value fields", self.name, fd.name)))
+                }
+                _ => {}
+            }
+
+            fields.push(fc);
+        }
+
+        let mut types = vec![];
+
+        for (i, t) in self.types.iter().enumerate() {
+            if self.types[..i].iter().any(|x| x.name == t.name) {
+                return Err(invalid(format!("{}: type {} is declared twice", self.name, t.name)))
+            }
+
+            let mut udt_fields = vec![];
+
+            for fd in t.fields.iter() {
+                udt_fields.push(try!(self.field(fd)));
+            }
+
+            types.push(new_udt(&t.name, udt_fields));
+        }
+
+        if let Err(e) = types_in_order(&types) {
+            return Err(invalid(format!("{}: {}", self.name, e)))
+        }
+
+        let mut conf = new_conf(&self.name, (), if fields.len() > 0 { Some(fields) } else { None }, None, None);
+
+        if self.by_entity.len() > 0 {
+            conf.by_entity = Some(self.by_entity.iter().map(|x| &x[..]).collect());
+        }
+
+        if self.by_many.len() > 0 {
+            conf.by_many = Some(self.by_many.iter().map(|x| &x[..]).collect());
+        }
+
+        if types.len() > 0 {
+            conf.types = Some(types);
+        }
+
+        conf.ttl = self.ttl;
+        conf.counters = self.counters;
+        conf.soft_delete = self.soft_delete;
+
+        if let Some(ref b) = self.bucketing {
+            conf.bucketing = Some(try!(self.bucketing(b)));
+        }
+
+        Ok(conf)
+    }
+    fn bucketing(&self, b: &str) -> Result<Bucketing> {
+        let err = || invalid(format!("{}: unknown bucketing {}", self.name, b));
+
+        let mut parts = b.trim().splitn(2, ':');
+
+        let kind = parts.next().unwrap_or("");
+        let n = try!(parts.next().and_then(|x| x.trim().parse::<u32>().ok()).filter(|&x| x > 0).ok_or_else(err));
+
+        match kind {
+            "day" => Ok(Bucketing::Day(n)),
+            "month" => Ok(Bucketing::Month(n)),
+            "hash" => Ok(Bucketing::Hash(n)),
+            _ => Err(err())
+        }
+    }
+    fn field_conf<'a>(&'a self, fd: &'a FieldDef) -> Result<FieldConf<'a>> {
+        let f = try!(self.field(fd));
+
+        let qt = match fd.query.as_ref().map(|x| &x[..]) {
+            None | Some("storaged") => QueryType::Storaged,
+            Some("value") => QueryType::Value,
+            Some("substring") => QueryType::Substring,
+            Some(other) => return Err(invalid(format!("{}.{}: unknown query type {}", self.name, fd.name, other)))
+        };
+
+        let mut fc = match fd.order.as_ref().map(|x| &x[..]) {
+            None => new_fc(f, qt),
+            Some("asc") => new_ordered_fc(f, qt, Order::Asc),
+            Some("desc") => new_ordered_fc(f, qt, Order::Desc),
+            Some(other) => return Err(invalid(format!("{}.{}: unknown order {}", self.name, fd.name, other)))
+        };
+
+        fc.nullable = fd.nullable;
+
+        Ok(fc)
+    }
+    fn field<'a>(&'a self, fd: &'a FieldDef) -> Result<Field<'a>> {
+        let name = &fd.name[..];
+        let kind = fd.kind.trim();
+
+        let err = || invalid(format!("{}.{}: unknown type {}", self.name, name, kind));
+
+        match kind {
+            "bigint" => return Ok(Field::Bigint(name)),
+            "timestamp" => return Ok(Field::Timestamp(name)),
+            "text" => return Ok(Field::Text(name)),
+            "double" => return Ok(Field::Double(name)),
+            "counter" => return Err(invalid(format!("{}.{}: counters can't be mixed with other fields, use a CounterConf", self.name, name))),
+            _ => {}
+        }
+
+        if let Some(inner) = generic(kind, "list") {
+            return Ok(Field::List(name, try!(self.cql_type(inner).ok_or_else(&err))))
+        }
+
+        if let Some(inner) = generic(kind, "set") {
+            return Ok(Field::Set(name, try!(self.cql_type(inner).ok_or_else(&err))))
+        }
+
+        if let Some(inner) = generic(kind, "map") {
+            let mut parts = inner.splitn(2, ',');
+
+            let k = try!(parts.next().and_then(|x| self.cql_type(x)).ok_or_else(&err));
+            let v = try!(parts.next().and_then(|x| self.cql_type(x)).ok_or_else(&err));
+
+            return Ok(Field::Map(name, k, v))
+        }
+
+        match self.cql_type(kind) {
+            Some(CqlType::Udt(udt)) => Ok(Field::Udt(name, udt)),
+            _ => Err(err())
+        }
+    }
+    fn cql_type<'a>(&'a self, kind: &'a str) -> Option<CqlType<'a>> {
+        let kind = kind.trim();
+
+        match kind {
+            "bigint" => Some(CqlType::Bigint),
+            "timestamp" => Some(CqlType::Timestamp),
+            "text" => Some(CqlType::Text),
+            "double" => Some(CqlType::Double),
+            _ => {
+                let udt = generic(kind, "frozen").unwrap_or(kind).trim();
+
+                self.types.iter().find(|t| t.name == udt).map(|t| CqlType::Udt(&t.name[..]))
+            }
+        }
+    }
+}
+
+// inner of list<...>
+fn generic<'a>(kind: &'a str, name: &str) -> Option<&'a str> {
+    if kind.starts_with(name) && kind.ends_with('>') {
+        let rest = kind[name.len()..].trim_start();
+
+        if rest.starts_with('<') {
+            return Some(&rest[1..rest.len() - 1])
+        }
+    }
+
+    None
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
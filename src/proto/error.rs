@@ -0,0 +1,106 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+
+// errors of the typed api, io errors come from the connection and the server
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    // the row has no column for the field
+    Missing(String),
+    // more values than fields to write them to
+    Arity { expected: usize, got: usize },
+    // the column of the field is not of the expected type
+    Type(String, &'static str),
+    // the value names a field the Conf does not have
+    Unknown(String),
+    // null for a field that is not nullable
+    NotNullable(String),
+    // a column that has no json form inside a nested value
+    Nested(String),
+    // the user-defined type depends on itself through other types
+    Cycle(String),
+    // Day and Month bucketing need simpleflake ids
+    NotSimpleflake(i64)
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Error::Io(ref e) => write!(f, "{}", e),
+            &Error::Missing(ref field) => write!(f, "field {} is missing", field),
+            &Error::Arity { expected, got } => write!(f, "{} values for {} fields", got, expected),
+            &Error::Type(ref field, expected) => write!(f, "field {} is not of type {}", field, expected),
+            &Error::Unknown(ref field) => write!(f, "field {} is unknown", field),
+            &Error::NotNullable(ref field) => write!(f, "field {} is not nullable", field),
+            &Error::Nested(ref column) => write!(f, "column {} can't be nested", column),
+            &Error::Cycle(ref udt) => write!(f, "type {} depends on itself", udt),
+            &Error::NotSimpleflake(id) => write!(f, "id {} is not a simpleflake id", id)
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match self {
+            &Error::Io(ref e) => e.description(),
+            &Error::Missing(_) => "missing field",
+            &Error::Arity { .. } => "too many values",
+            &Error::Type(_, _) => "mistyped field",
+            &Error::Unknown(_) => "unknown field",
+            &Error::NotNullable(_) => "field not nullable",
+            &Error::Nested(_) => "column not nestable",
+            &Error::Cycle(_) => "cyclic type",
+            &Error::NotSimpleflake(_) => "not a simpleflake id"
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        // typed errors travel through io::Result as the inner error of an InvalidInput
+        if e.get_ref().map_or(false, |inner| inner.is::<Error>()) {
+            return *e.into_inner().unwrap().downcast::<Error>().unwrap();
+        }
+
+        Error::Io(e)
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> io::Error {
+        match e {
+            Error::Io(e) => e,
+            e => io::Error::new(io::ErrorKind::InvalidInput, e)
+        }
+    }
+}
+
+// an error the server answered a statement with, the host itself is fine
+#[derive(Debug)]
+pub struct ServerError {
+    pub code: i32,
+    pub message: String
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "error {}: {}", self.code, self.message)
+    }
+}
+
+impl error::Error for ServerError {
+    fn description(&self) -> &str {
+        "server error"
+    }
+}
+
+pub fn server_error(code: i32, message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, ServerError { code: code, message: message })
+}
+
+pub fn server_error_of(e: &io::Error) -> Option<&ServerError> {
+    e.get_ref().and_then(|inner| inner.downcast_ref::<ServerError>())
+}
@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::hash::Hasher;
+
+use chrono::*;
+
+
+// simpleflake ids: milliseconds since 2000-01-01 in the high 41 bits, 23 low bits to tell ids of the same millisecond apart
+// they sort by creation time, which is the id desc order of the main table
+
+pub const SIMPLEFLAKE_EPOCH: i64 = 946684800000;
+pub const SIMPLEFLAKE_SHIFT: u32 = 23;
+pub const SIMPLEFLAKE_LOW_MASK: i64 = (1 << SIMPLEFLAKE_SHIFT) - 1;
+// a sequence keeps its node in the low bits above this, 10 bits of node and 13 of sequence
+pub const SEQUENCE_NODE_SHIFT: u32 = 13;
+
+pub trait IdGenerator {
+    fn next_id(&mut self, group: i64) -> i64;
+}
+
+// random low bits, ids of different processes don't collide
+pub struct Simpleflake {
+    random: RandomState,
+    counter: u64
+}
+
+pub fn new_simpleflake() -> Simpleflake {
+    Simpleflake {
+        random: RandomState::new(),
+        counter: 0
+    }
+}
+
+impl IdGenerator for Simpleflake {
+    fn next_id(&mut self, group: i64) -> i64 {
+        let mut hasher = self.random.build_hasher();
+
+        hasher.write_u64(self.counter);
+        hasher.write_i64(group);
+
+        self.counter += 1;
+
+        id_at(now_millis()) | (hasher.finish() as i64 & SIMPLEFLAKE_LOW_MASK)
+    }
+}
+
+// a node and a sequence per group in the low bits, ids of a group strictly increase within the process
+// and sequences of different nodes start each millisecond apart
+pub struct Sequence {
+    node: i64,
+    last: HashMap<i64, i64>
+}
+
+// a random node, like the low bits of Simpleflake
+pub fn new_sequence() -> Sequence {
+    new_sequence_on(RandomState::new().build_hasher().finish() as i64)
+}
+
+// only the low 10 bits of node are kept
+pub fn new_sequence_on(node: i64) -> Sequence {
+    Sequence {
+        node: (node & (SIMPLEFLAKE_LOW_MASK >> SEQUENCE_NODE_SHIFT)) << SEQUENCE_NODE_SHIFT,
+        last: HashMap::new()
+    }
+}
+
+impl IdGenerator for Sequence {
+    fn next_id(&mut self, group: i64) -> i64 {
+        let t = id_at(now_millis()) | self.node;
+
+        let last = self.last.entry(group).or_insert(0);
+
+        // more than 2^13 ids in a millisecond run into the next node's, then borrow from the next millisecond
+        *last = if t > *last { t } else { *last + 1 };
+
+        *last
+    }
+}
+
+// smallest id of the millisecond
+pub fn id_at(ms: i64) -> i64 {
+    (ms - SIMPLEFLAKE_EPOCH) << SIMPLEFLAKE_SHIFT
+}
+
+pub fn id_millis(id: i64) -> i64 {
+    (id >> SIMPLEFLAKE_SHIFT) + SIMPLEFLAKE_EPOCH
+}
+
+pub fn id_timestamp(id: i64) -> Option<DateTime<UTC>> {
+    millis_timestamp(id_millis(id))
+}
+
+// None past the dates chrono holds
+pub fn millis_timestamp(ms: i64) -> Option<DateTime<UTC>> {
+    UTC.timestamp_opt(ms.div_euclid(1000), (ms.rem_euclid(1000) * 1000000) as u32).single()
+}
+
+// smallest id created at t or later
+pub fn id_at_time(t: &DateTime<UTC>) -> i64 {
+    id_at(t.timestamp() * 1000 + (t.nanosecond() / 1000000) as i64)
+}
+
+fn now_millis() -> i64 {
+    let t = UTC::now();
+
+    t.timestamp() * 1000 + (t.nanosecond() / 1000000) as i64
+}
@@ -25,10 +25,10 @@ pub fn field_create_job<T>(conf: &Conf<T>, schema: &mut Schema, fc: &FieldConf)
             storaged_create_job(conf, schema, &fc.f);
         }
         QueryType::Value => {
-            by_field_create_job(conf, schema, &fc.f);
+            by_field_create_job(conf, schema, fc);
         }
         QueryType::Substring => {
-            by_field_create_job(conf, schema, &fc.f);
+            by_field_create_job(conf, schema, fc);
             by_substring_create_job(conf, schema, &fc.f);
         }
     }
@@ -36,24 +36,30 @@ pub fn field_create_job<T>(conf: &Conf<T>, schema: &mut Schema, fc: &FieldConf)
 
 pub fn storaged_create_job<T>(conf: &Conf<T>, schema: &mut Schema, f: &Field) {
 }
-pub fn by_field_create_job<T>(conf: &Conf<T>, schema: &mut Schema, f: &Field) {
+pub fn by_field_create_job<T>(conf: &Conf<T>, schema: &mut Schema, fc: &FieldConf) {
     // (group id) f1 f2 ... fn                                  main
     // (group f1 id) f2 ... fn                                  Value
+    let f = &fc.f;
     let field_name = f.get_name();
-    let mut query = "create table ".to_string() + conf.name + "_by_field_" + field_name + " (group bigint,
+    let table = conf.name.to_string() + "_by_field_" + field_name;
+    let mut query = "create table ".to_string() + &table + " (group bigint," + conf.bucket_column() + "
         id bigint, created_at timestamp, updated_at timestamp,";
     match conf.fields {
-        Some(ref f) => {
-            query = f.iter().fold(query, |query, x| {
-                add_field(query, &x.f)
+        Some(ref fields) => {
+            query = fields.iter().fold(query, |query, x| {
+                if x.f.get_name() == field_name {
+                    query + field_name + " " + &f.get_index_type() + ","
+                } else {
+                    add_field(query, &x.f)
+                }
             });
         }
         _ => {}
     }
 
-    query = query + "primary key (group," + field_name + ",id)
+    query = query + "primary key (" + conf.partition_key() + "," + field_name + ",id)
         )
-        with clustering order by (" + field_name + " " + f.get_order() + ",id desc) and gc_grace_seconds = 86400";
+        with clustering order by (" + field_name + " " + fc.get_order() + ",id desc) and " + &conf.get_table_options(&table);
 
     //println!("");
     //println!("{}", query);
@@ -65,9 +71,9 @@ pub fn by_substring_create_job<T>(conf: &Conf<T>, schema: &mut Schema, f: &Field
     // (group f1 id) f2 ... fn + (group f1_substring f1)        Substring
 
     let field_name = f.get_name();
-    let mut query = "create table ".to_string()
-    + conf.name + "_" + field_name
-    + "_substring (group bigint,substring text,value text, primary key (group,substring,value)) with gc_grace_seconds = 86400";
+    let table = conf.name.to_string() + "_" + field_name + "_substring";
+    let mut query = "create table ".to_string() + &table
+    + " (group bigint,substring text,value text, primary key (group,substring,value)) with " + &conf.get_table_options(&table);
 
     //println!("");
     //println!("{}", query);
@@ -77,7 +83,8 @@ pub fn by_substring_create_job<T>(conf: &Conf<T>, schema: &mut Schema, f: &Field
 pub fn by_entity_create_job<T>(conf: &Conf<T>, schema: &mut Schema, entity_name: &str) {
     // (group id) f1 f2 ... fn                                  main
     // (group entity_id id) f1 f2 ... fn                        by_entity
-    let mut query = "create table ".to_string() + conf.name + "_by_entity_" + entity_name
+    let table = conf.name.to_string() + "_by_entity_" + entity_name;
+    let mut query = "create table ".to_string() + &table
     + " (group bigint, entity bigint, id bigint, created_at timestamp, updated_at timestamp,";
     match conf.fields {
         Some(ref f) => {
@@ -90,7 +97,7 @@ pub fn by_entity_create_job<T>(conf: &Conf<T>, schema: &mut Schema, entity_name:
 
     query = query + "primary key (group,entity,id)
         )
-        with clustering order by (entity desc,id desc) and gc_grace_seconds = 86400";
+        with clustering order by (entity desc,id desc) and " + &conf.get_table_options(&table);
 
     //println!("");
     //println!("{}", query);
@@ -100,7 +107,8 @@ pub fn by_entity_create_job<T>(conf: &Conf<T>, schema: &mut Schema, entity_name:
 pub fn by_many_create_job<T>(conf: &Conf<T>, schema: &mut Schema, entity_name: &str) {
     // (group id) f1 f2 ... fn                                  main
     // (group entity_id id row) f1 f2 ... fn                    by_many
-    let mut query = "create table ".to_string() + conf.name + "_by_many_" + entity_name
+    let table = conf.name.to_string() + "_by_many_" + entity_name;
+    let mut query = "create table ".to_string() + &table
     + " (group bigint,entity bigint,id bigint,row bigint,created_at timestamp,updated_at timestamp,";
     match conf.fields {
         Some(ref f) => {
@@ -113,10 +121,35 @@ pub fn by_many_create_job<T>(conf: &Conf<T>, schema: &mut Schema, entity_name: &
 
     query = query + "primary key (group,entity,id,row)
         )
-        with clustering order by (entity desc,id desc, row desc) and gc_grace_seconds = 86400";
+        with clustering order by (entity desc,id desc, row desc) and " + &conf.get_table_options(&table);
 
     //println!("");
     //println!("{}", query);
 
     schema.queries.push(query);
-}
\ No newline at end of file
+}
+pub fn counters_create_job<T>(conf: &Conf<T>, schema: &mut Schema) {
+    // (group) count                                            counters
+    // (group f1) count                                         counters by Value
+
+    let table = conf.name.to_string() + "_counts";
+    let query = "create table ".to_string() + &table
+    + " (group bigint, count counter, primary key (group)) with " + &conf.get_counter_table_options(&table);
+
+    schema.queries.push(query);
+
+    if let Some(ref fields) = conf.fields {
+        for fc in fields.iter() {
+            if let QueryType::Storaged = fc.qt {
+                continue;
+            }
+
+            let field_name = fc.f.get_name();
+            let table = conf.name.to_string() + "_counts_by_field_" + field_name;
+            let query = "create table ".to_string() + &table
+            + " (group bigint, " + field_name + " " + &fc.f.get_index_type() + ", count counter, primary key (group," + field_name + ")) with " + &conf.get_counter_table_options(&table);
+
+            schema.queries.push(query);
+        }
+    }
+}
@@ -19,25 +19,39 @@ use proto::schema::Schema;
 
 
 
-pub fn field_delete_job<T>(conf: &Conf<T>, fc: &FieldConf, group: i64, id: i64, mut values: Vec<Column>, state: HashMap<String, Column>, fs: Column) -> Option<BatchQuery> {
+pub fn field_delete_job<T>(conf: &Conf<T>, fc: &FieldConf, group: i64, id: i64, mut values: Vec<Column>, state: HashMap<String, Column>, fs: Column, new: &Column, opts: &WriteOptions) -> Vec<BatchQuery> {
     match fc.qt {
         QueryType::Storaged => {
             storaged_delete_job(conf, &fc.f, group, id, values.clone());
-            None
+            vec![]
         }
         QueryType::Value =>  {
-            Some(by_field_delete_job(conf, &fc.f, group, id, values.clone(), state.clone(), fs.clone()))
+            by_value_delete_jobs(conf, &fc.f, group, id, values.clone(), state.clone(), fs.clone(), new, opts)
         }
         QueryType::Substring => {
             by_substring_delete_job(conf, &fc.f, group, id, values.clone());
-            Some(by_field_delete_job(conf, &fc.f, group, id, values.clone(), state.clone(), fs.clone()))
+            by_value_delete_jobs(conf, &fc.f, group, id, values.clone(), state.clone(), fs.clone(), new, opts)
+        }
+    }
+}
+
+pub fn by_value_delete_jobs<T>(conf: &Conf<T>, f: &Field, group: i64, id: i64, mut values: Vec<Column>, state: HashMap<String, Column>, fs: Column, new: &Column, opts: &WriteOptions) -> Vec<BatchQuery> {
+    match f {
+        &Field::Set(_, _) => {
+            // only elements that are gone, a delete and an insert of the same row in one batch share a timestamp and the delete wins
+            removed_elements(&fs, new).into_iter().map(|e| {
+                by_field_delete_job(conf, f, group, id, values.clone(), state.clone(), e, opts)
+            }).collect()
+        }
+        _ => {
+            vec![by_field_delete_job(conf, f, group, id, values.clone(), state.clone(), fs.clone(), opts)]
         }
     }
 }
 
 pub fn storaged_delete_job<T>(conf: &Conf<T>, f: &Field, group: i64, id: i64, mut values: Vec<Column>) {
 }
-pub fn by_field_delete_job<T>(conf: &Conf<T>, f: &Field, group: i64, id: i64, mut values: Vec<Column>, state: HashMap<String, Column>, fs: Column) -> BatchQuery {
+pub fn by_field_delete_job<T>(conf: &Conf<T>, f: &Field, group: i64, id: i64, mut values: Vec<Column>, state: HashMap<String, Column>, fs: Column, opts: &WriteOptions) -> BatchQuery {
 
     // (group id) f1 f2 ... fn                                  main
     // (group f1 id) f2 ... fn                                  Value
@@ -45,15 +59,16 @@ pub fn by_field_delete_job<T>(conf: &Conf<T>, f: &Field, group: i64, id: i64, mu
     let field_name = f.get_name();
 
     let mut query = "delete from test1.".to_string() + conf.name + "_by_field_"
-    + field_name + " where group = ? and " + field_name + " = ? and id = ?";
+    + field_name + &opts.using_timestamp() + " where " + conf.partition_where() + " and " + field_name + " = " + placeholder(f) + " and id = ?";
 
     //println!("{}", query);
 
-    BatchQuery::SimpleWithParams(query, vec![
-    Column::Bigint(group),
-    fs,
-    Column::Bigint(id)
-    ])
+    let mut values = conf.partition_values(group, id);
+
+    values.push(fs);
+    values.push(Column::Bigint(id));
+
+    BatchQuery::SimpleWithParams(query, opts.using_then_values(values))
 
 }
 pub fn by_substring_delete_job<T>(conf: &Conf<T>, f: &Field, group: i64, id: i64, mut values: Vec<Column>) {
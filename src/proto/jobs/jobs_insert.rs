@@ -15,35 +15,36 @@ use proto::shared::*;
 use proto::conf::*;
 
 use proto::schema::Schema;
+use proto::udt::bind_values;
 
 
 
-pub fn field_insert_job<T>(conf: &Conf<T>, fc: &FieldConf, fields: &Vec<&FieldConf>, group: i64, id: i64, mut values: Vec<Column>) -> Option<BatchQuery> {
+pub fn field_insert_job<T>(conf: &Conf<T>, fc: &FieldConf, fields: &Vec<&FieldConf>, group: i64, id: i64, mut values: Vec<Column>, opts: &WriteOptions) -> Vec<BatchQuery> {
     match fc.qt {
         QueryType::Storaged => {
             storaged_insert_job(conf, &fc.f, fields, group, id, values.clone());
-            None
+            vec![]
         }
         QueryType::Value =>  {
-            Some(by_field_insert_job(conf, &fc.f, fields, group, id, values.clone()))
+            by_field_insert_job(conf, &fc.f, fields, group, id, values.clone(), opts)
         }
         QueryType::Substring => {
             by_substring_insert_job(conf, &fc.f, fields, group, id, values.clone());
-            Some(by_field_insert_job(conf, &fc.f, fields, group, id, values.clone()))
+            by_field_insert_job(conf, &fc.f, fields, group, id, values.clone(), opts)
         }
     }
 }
 
 pub fn storaged_insert_job<T>(conf: &Conf<T>, f: &Field, fields: &Vec<&FieldConf>, group: i64, id: i64, mut values: Vec<Column>) {
 }
-pub fn by_field_insert_job<T>(conf: &Conf<T>, f: &Field, fields: &Vec<&FieldConf>, group: i64, id: i64, mut values: Vec<Column>) -> BatchQuery {
+pub fn by_field_insert_job<T>(conf: &Conf<T>, f: &Field, fields: &Vec<&FieldConf>, group: i64, id: i64, mut values: Vec<Column>, opts: &WriteOptions) -> Vec<BatchQuery> {
 
     // (group id) f1 f2 ... fn                                  main
     // (group f1 id) f2 ... fn                                  Value
 
     let field_name = f.get_name();
 
-    let mut query = "insert into test1.".to_string() + conf.name + "_by_field_" + field_name + " (group,id,";
+    let mut query = "insert into test1.".to_string() + conf.name + "_by_field_" + field_name + " (" + conf.partition_columns() + "id,";
 
     if fields.len() != values.len() {
         panic!("fields and values count don't match");
@@ -57,25 +58,49 @@ pub fn by_field_insert_job<T>(conf: &Conf<T>, f: &Field, fields: &Vec<&FieldConf
 
     query.truncate(len - 1);
 
-    query = query + ") values (?,?,";
+    query = query + ") values (" + conf.partition_placeholders() + "?,";
 
-    for i in 0..values.len() {
-        query = query + "?,";
-    }
+    query = fields.iter().fold(query, |query, x| add_placeholder(query, &x.f));
 
     let len = query.len();
 
     query.truncate(len - 1);
 
-    query = query + ")";
+    query = query + ")" + &opts.using();
 
     //println!("{}", query);
 
-    values.insert(0, Column::Bigint(id));
-    values.insert(0, Column::Bigint(group));
+    match f {
+        &Field::Set(_, _) => {
+            // one row per element of the set
+            match fields.iter().position(|x| x.f.get_name() == field_name) {
+                Some(i) => {
+                    by_element_rows(query, &conf.key_values(group, id), fields, values, i, opts)
+                }
+                None => vec![]
+            }
+        }
+        _ => {
+            vec![BatchQuery::SimpleWithParams(query, opts.values_then_using(conf.keyed(group, id, bind_values(fields, values))))]
+        }
+    }
+
+}
+// key is the partition values and id the row goes under, values are those of fields and i is the set
+pub fn by_element_rows(query: String, key: &Vec<Column>, fields: &[&FieldConf], values: Vec<Column>, i: usize, opts: &WriteOptions) -> Vec<BatchQuery> {
+    let items = elements(&values[i]);
+
+    let values = opts.values_then_using(bind_values(fields, values));
+
+    items.into_iter().map(|e| {
+        let mut row = key.clone();
+
+        row.extend(values.iter().cloned());
 
-    BatchQuery::SimpleWithParams(query, values)
+        row[key.len() + i] = e;
 
+        BatchQuery::SimpleWithParams(query.clone(), row)
+    }).collect()
 }
 pub fn by_substring_insert_job<T>(conf: &Conf<T>, f: &Field, fields: &Vec<&FieldConf>, group: i64, id: i64, mut values: Vec<Column>) {
     // (group id) f1 f2 ... fn                                  main
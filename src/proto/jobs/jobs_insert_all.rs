@@ -13,37 +13,39 @@ use rustcql::shared::Column;
 
 use proto::shared::*;
 use proto::conf::*;
+use proto::jobs::jobs_insert::by_element_rows;
+use proto::udt::bind_values;
 
 use proto::schema::Schema;
 
 
 
-pub fn field_insert_all_job<T>(conf: &Conf<T>, fc: &FieldConf, group: i64, id: i64, mut values: Vec<Column>) -> Option<BatchQuery> {
+pub fn field_insert_all_job<T>(conf: &Conf<T>, fc: &FieldConf, group: i64, id: i64, mut values: Vec<Column>, opts: &WriteOptions) -> Vec<BatchQuery> {
     match fc.qt {
         QueryType::Storaged => {
             storaged_insert_all_job(conf, &fc.f, group, id, values.clone());
-            None
+            vec![]
         }
         QueryType::Value =>  {
-            Some(by_field_insert_all_job(conf, &fc.f, group, id, values.clone()))
+            by_field_insert_all_job(conf, &fc.f, group, id, values.clone(), opts)
         }
         QueryType::Substring => {
             by_substring_insert_all_job(conf, &fc.f, group, id, values.clone());
-            Some(by_field_insert_all_job(conf, &fc.f, group, id, values.clone()))
+            by_field_insert_all_job(conf, &fc.f, group, id, values.clone(), opts)
         }
     }
 }
 
 pub fn storaged_insert_all_job<T>(conf: &Conf<T>, f: &Field, group: i64, id: i64, mut values: Vec<Column>) {
 }
-pub fn by_field_insert_all_job<T>(conf: &Conf<T>, f: &Field, group: i64, id: i64, mut values: Vec<Column>) -> BatchQuery {
+pub fn by_field_insert_all_job<T>(conf: &Conf<T>, f: &Field, group: i64, id: i64, mut values: Vec<Column>, opts: &WriteOptions) -> Vec<BatchQuery> {
 
     // (group id) f1 f2 ... fn                                  main
     // (group f1 id) f2 ... fn                                  Value
 
     let field_name = f.get_name();
 
-    let mut query = "insert into test1.".to_string() + conf.name + "_by_field_" + field_name + " (group,id,";
+    let mut query = "insert into test1.".to_string() + conf.name + "_by_field_" + field_name + " (" + conf.partition_columns() + "id,";
 
     if let Some(ref f) = conf.fields {
 
@@ -61,26 +63,41 @@ pub fn by_field_insert_all_job<T>(conf: &Conf<T>, f: &Field, group: i64, id: i64
 
     query.truncate(len - 1);
 
-    query = query + ") values (?,?,";
+    query = query + ") values (" + conf.partition_placeholders() + "?,";
 
-    for i in 0..values.len() {
-        query = query + "?,";
+    if let Some(ref f) = conf.fields {
+        query = f.iter().fold(query, |query, x| add_placeholder(query, &x.f));
     }
 
     let len = query.len();
 
     query.truncate(len - 1);
 
-    query = query + ")";
+    query = query + ")" + &opts.using();
 
     //println!("{}", query);
 
-    values.insert(0, Column::Bigint(id));
-    values.insert(0, Column::Bigint(group));
+    if let &Field::Set(_, _) = f {
+        // one row per element of the set
+        if let Some(ref fields) = conf.fields {
+            if let Some(i) = fields.iter().position(|x| x.f.get_name() == field_name) {
+                return by_element_rows(query, &conf.key_values(group, id), &fields.iter().collect::<Vec<_>>(), values, i, opts)
+            }
+        }
+
+        return vec![]
+    }
+
+    let values = match conf.fields {
+        Some(ref fields) => bind_values(&fields.iter().collect::<Vec<_>>(), values),
+        None => values
+    };
+
+    let values = opts.values_then_using(conf.keyed(group, id, values));
 
     //println!("{:?}", values);
 
-    BatchQuery::SimpleWithParams(query, values)
+    vec![BatchQuery::SimpleWithParams(query, values)]
 
 }
 pub fn by_substring_insert_all_job<T>(conf: &Conf<T>, f: &Field, group: i64, id: i64, mut values: Vec<Column>) {
@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::result;
+
+use chrono::*;
+
+use serde::de::DeserializeSeed;
+use serde::de::Deserializer;
+use serde::de::Error as DeError;
+use serde::ser::Error as SerError;
+use serde::ser::Serialize;
+use serde::ser::SerializeMap;
+use serde::ser::SerializeSeq;
+use serde::ser::Serializer;
+use serde_json;
+use serde_json::Value;
+
+use rustcql::shared::Consistency;
+use rustcql::shared::Response;
+use rustcql::shared::Column;
+
+use proto::shared::*;
+use proto::conf::*;
+use proto::session::Session;
+use proto::error::Error;
+use proto::check::expected_type;
+use proto::id::millis_timestamp;
+
+
+// rows as json for frontends
+// timestamps are rfc3339, or the raw milliseconds past the dates chrono holds, bigints beyond what a javascript number holds exactly are strings
+// reading json back needs the Field, the json alone does not say bigint or timestamp
+
+pub const MAX_SAFE_INTEGER: i64 = 9007199254740991;
+
+pub struct JsonColumn<'a>(pub &'a Column);
+
+pub struct JsonRow<'a>(pub &'a HashMap<String, Column>);
+
+impl<'a> Serialize for JsonColumn<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> result::Result<S::Ok, S::Error> {
+        match self.0 {
+            &Column::Bigint(v) => {
+                if v >= -MAX_SAFE_INTEGER && v <= MAX_SAFE_INTEGER {
+                    serializer.serialize_i64(v)
+                } else {
+                    serializer.serialize_str(&v.to_string())
+                }
+            }
+            &Column::Double(v) => serializer.serialize_f64(v),
+            &Column::String(ref s) => serializer.serialize_str(s),
+            &Column::Timestamp(ms) => match rfc3339(ms) {
+                Some(s) => serializer.serialize_str(&s),
+                None => serializer.serialize_i64(ms)
+            },
+            &Column::List(ref items) | &Column::Set(ref items) => {
+                let mut seq = try!(serializer.serialize_seq(Some(items.len())));
+                for item in items.iter() {
+                    try!(seq.serialize_element(&JsonColumn(item)));
+                }
+                seq.end()
+            }
+            &Column::Map(ref items) => {
+                let mut map = try!(serializer.serialize_map(Some(items.len())));
+                for &(ref k, ref v) in items.iter() {
+                    try!(map.serialize_entry(&JsonColumn(k), &JsonColumn(v)));
+                }
+                map.end()
+            }
+            c => Err(S::Error::custom(format!("no json for column {:?}", c)))
+        }
+    }
+}
+
+impl<'a> Serialize for JsonRow<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> result::Result<S::Ok, S::Error> {
+        let mut map = try!(serializer.serialize_map(Some(self.0.len())));
+        for (k, v) in self.0.iter() {
+            try!(map.serialize_entry(k, &JsonColumn(v)));
+        }
+        map.end()
+    }
+}
+
+// keys of maps are bigints, strings or timestamps, all of them serialize as json keys,
+// a column json has no value for is an error
+pub fn row_to_json(row: &HashMap<String, Column>) -> result::Result<Value, serde_json::Error> {
+    serde_json::to_value(JsonRow(row))
+}
+
+pub fn rfc3339(ms: i64) -> Option<String> {
+    millis_timestamp(ms).map(|t| t.to_rfc3339())
+}
+
+// deserializes a column of the type of the field
+pub struct JsonField<'a, 'b: 'a>(pub &'a Field<'b>);
+
+impl<'de, 'a, 'b> DeserializeSeed<'de> for JsonField<'a, 'b> {
+    type Value = Column;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> result::Result<Column, D::Error> {
+        let value = try!(<Value as ::serde::Deserialize>::deserialize(deserializer));
+
+        column_from_json(&value, self.0).map_err(|e| D::Error::custom(e))
+    }
+}
+
+pub fn column_from_json(value: &Value, f: &Field) -> result::Result<Column, Error> {
+    let name = f.get_name();
+
+    match f {
+        &Field::Bigint(_) => element_from_json(value, &CqlType::Bigint, name),
+        &Field::Timestamp(_) => element_from_json(value, &CqlType::Timestamp, name),
+        &Field::Text(_) => element_from_json(value, &CqlType::Text, name),
+        &Field::Double(_) => element_from_json(value, &CqlType::Double, name),
+        &Field::Udt(_, udt) => element_from_json(value, &CqlType::Udt(udt), name),
+        &Field::List(_, ref t) | &Field::Set(_, ref t) => {
+            let items = match value {
+                &Value::Array(ref items) => items,
+                _ => return Err(Error::Type(name.to_string(), "array"))
+            };
+
+            let mut res = vec![];
+            for item in items.iter() {
+                res.push(try!(element_from_json(item, t, name)));
+            }
+
+            match f {
+                &Field::List(_, _) => Ok(Column::List(res)),
+                _ => Ok(Column::Set(res))
+            }
+        }
+        &Field::Map(_, ref k, ref v) => {
+            let items = match value {
+                &Value::Object(ref items) => items,
+                _ => return Err(Error::Type(name.to_string(), "object"))
+            };
+
+            let mut res = vec![];
+            for (key, item) in items.iter() {
+                res.push((
+                    try!(element_from_json(&Value::String(key.clone()), k, name)),
+                    try!(element_from_json(item, v, name))
+                ));
+            }
+
+            Ok(Column::Map(res))
+        }
+    }
+}
+
+fn element_from_json(value: &Value, t: &CqlType, name: &str) -> result::Result<Column, Error> {
+    match (t, value) {
+        (&CqlType::Bigint, &Value::Number(ref n)) => n.as_i64().map(Column::Bigint),
+        (&CqlType::Bigint, &Value::String(ref s)) => s.parse().ok().map(Column::Bigint),
+        (&CqlType::Timestamp, &Value::Number(ref n)) => n.as_i64().map(Column::Timestamp),
+        (&CqlType::Timestamp, &Value::String(ref s)) => {
+            DateTime::parse_from_rfc3339(s).ok().map(|d| {
+                Column::Timestamp(d.timestamp() * 1000 + (d.nanosecond() / 1000000) as i64)
+            })
+        }
+        (&CqlType::Double, &Value::Number(ref n)) => n.as_f64().map(Column::Double),
+        (&CqlType::Text, &Value::String(ref s)) => Some(Column::String(s.clone())),
+        // udt values are written through fromJson(?)
+        (&CqlType::Udt(_), &Value::Object(_)) => Some(Column::String(value.to_string())),
+        _ => None
+    }.ok_or_else(|| Error::Type(name.to_string(), expected_type(t)))
+}
+
+impl<'a, T> Conf<'a, T> {
+    // every key of the object must be a field of the Conf
+    pub fn hashmap_from_json(&self, value: &Value) -> result::Result<HashMap<String, Column>, Error> {
+        let items = match value {
+            &Value::Object(ref items) => items,
+            _ => return Err(Error::Type(self.name.to_string(), "object"))
+        };
+
+        let mut res = HashMap::new();
+
+        for (k, v) in items.iter() {
+            let fc = match self.fields.as_ref().and_then(|fields| fields.iter().find(|fc| fc.f.get_name() == k)) {
+                Some(fc) => fc,
+                None => return Err(Error::Unknown(k.clone()))
+            };
+
+            res.insert(k.clone(), try!(column_from_json(v, &fc.f)));
+        }
+
+        Ok(res)
+    }
+
+    pub fn insert_json(&self, conn: &mut Session, group: i64, id: i64, value: &Value, consistency: Consistency, opts: &WriteOptions) -> result::Result<Response, Error> {
+        let hashmap = try!(self.hashmap_from_json(value));
+
+        Ok(try!(self.insert_hashmap(conn, group, id, hashmap, None, consistency, opts)))
+    }
+}
@@ -2,6 +2,25 @@ mod tests;
 
 pub mod shared;
 pub mod schema;
+pub mod session;
+pub mod retry;
+pub mod batch;
+pub mod bulk;
+pub mod pool;
+#[cfg(feature = "async")]
+pub mod nonblocking;
+pub mod udt;
+pub mod options;
+pub mod def;
+pub mod value;
+pub mod error;
+pub mod row;
+pub mod check;
+pub mod id;
+pub mod bucket;
+pub mod counter;
+#[cfg(feature = "json")]
+pub mod json;
 pub mod conf;
 pub mod jobs;
 
@@ -0,0 +1,410 @@
+use std::collections::HashMap;
+use std::io::Result;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use chrono::*;
+
+use futures::FutureExt;
+use futures::TryFutureExt;
+use futures::future;
+
+use tokio::task;
+
+use rustcql::shared::Consistency;
+use rustcql::shared::Response;
+use rustcql::shared::BatchQuery;
+use rustcql::shared::Column;
+
+use proto::shared::*;
+use proto::schema::*;
+use proto::session::Session;
+use proto::batch::BatchMode;
+use proto::batch::CounterUpdate;
+use proto::conf::*;
+use proto::conf::conf_first::select_first;
+use proto::conf::conf_list::*;
+use proto::conf::conf_collection::*;
+use proto::conf::conf_delete::is_deleted;
+use proto::counter::CounterConf;
+use proto::counter::negate;
+
+
+// the blocking session runs on tokio's blocking pool, queries and batches come from the same Conf methods the sync api uses
+
+pub type Pending<'c, R> = Pin<Box<Future<Output = Result<R>> + Send + 'c>>;
+
+// what the async api runs on the blocking pool, a Session or a stand-in for it in tests
+pub trait Runner: Send + 'static {
+    fn create_schema(&mut self, context: Vec<Schema>, replication_factor: u32);
+    fn select_first(&mut self, queries: Vec<(String, Vec<Column>)>) -> Result<Option<HashMap<String, Column>>>;
+    fn select_all(&mut self, queries: Vec<(String, Vec<Column>)>) -> Result<Vec<HashMap<String, Column>>>;
+    fn execute_planned(&mut self, batch: Vec<BatchQuery>, consistency: Consistency, mode: &BatchMode, idempotent: bool) -> Result<Response>;
+    fn execute_with_counters(&mut self, batch: Vec<BatchQuery>, counters: Vec<CounterUpdate>, consistency: Consistency, mode: &BatchMode, idempotent: bool) -> Result<Response>;
+    fn execute_counters(&mut self, counters: Vec<CounterUpdate>, consistency: Consistency) -> Result<Response>;
+}
+
+impl Runner for Session {
+    fn create_schema(&mut self, context: Vec<Schema>, replication_factor: u32) {
+        create_schema(self, context, replication_factor)
+    }
+    fn select_first(&mut self, queries: Vec<(String, Vec<Column>)>) -> Result<Option<HashMap<String, Column>>> {
+        select_first(self, queries)
+    }
+    fn select_all(&mut self, queries: Vec<(String, Vec<Column>)>) -> Result<Vec<HashMap<String, Column>>> {
+        select_all(self, queries)
+    }
+    fn execute_planned(&mut self, batch: Vec<BatchQuery>, consistency: Consistency, mode: &BatchMode, idempotent: bool) -> Result<Response> {
+        Session::execute_planned(self, batch, consistency, mode, idempotent)
+    }
+    fn execute_with_counters(&mut self, batch: Vec<BatchQuery>, counters: Vec<CounterUpdate>, consistency: Consistency, mode: &BatchMode, idempotent: bool) -> Result<Response> {
+        Session::execute_with_counters(self, batch, counters, consistency, mode, idempotent)
+    }
+    fn execute_counters(&mut self, counters: Vec<CounterUpdate>, consistency: Consistency) -> Result<Response> {
+        Session::execute_counters(self, counters, consistency)
+    }
+}
+
+pub struct AsyncSession<S = Session> {
+    pub inner: Arc<Mutex<S>>
+}
+
+impl<S> Clone for AsyncSession<S> {
+    fn clone(&self) -> AsyncSession<S> {
+        AsyncSession {
+            inner: self.inner.clone()
+        }
+    }
+}
+
+pub fn new_async_session<S: Runner>(session: S) -> AsyncSession<S> {
+    AsyncSession {
+        inner: Arc::new(Mutex::new(session))
+    }
+}
+
+impl<S: Runner> AsyncSession<S> {
+    pub fn blocking<R, F>(&self, f: F) -> Pending<'static, R>
+        where F: FnOnce(&mut S) -> Result<R> + Send + 'static, R: Send + 'static {
+
+        let inner = self.inner.clone();
+
+        task::spawn_blocking(move || {
+            // a call that panicked left the session in no known state, later calls fail instead of using it
+            let mut session = try!(inner.lock().map_err(|_| Error::new(ErrorKind::Other, "a call on the session panicked")));
+
+            f(&mut session)
+        }).map(|r| {
+            match r {
+                Ok(r) => r,
+                Err(e) => Err(Error::new(ErrorKind::Other, e))
+            }
+        }).boxed()
+    }
+    pub fn create_schema(&self, context: Vec<Schema>, replication_factor: u32) -> Pending<'static, ()> {
+        self.blocking(move |s| {
+            s.create_schema(context, replication_factor);
+            Ok(())
+        })
+    }
+    pub fn first<'c, 'a, T: Sync>(&self, conf: &'c Conf<'a, T>, group: i64, fc: &FieldConf, key: Column) -> Pending<'static, Option<HashMap<String, Column>>> {
+        let queries = conf.first_queries(group, fc, key);
+
+        self.blocking(move |s| s.select_first(queries))
+    }
+    pub fn first_by_id<'c, 'a, T: Sync>(&self, conf: &'c Conf<'a, T>, group: i64, id: i64) -> Pending<'static, Option<HashMap<String, Column>>> {
+        self.first_by_id_with_deleted(conf, group, id).map_ok(|row| row.filter(|x| !is_deleted(x))).boxed()
+    }
+    pub fn first_by_id_with_deleted<'c, 'a, T: Sync>(&self, conf: &'c Conf<'a, T>, group: i64, id: i64) -> Pending<'static, Option<HashMap<String, Column>>> {
+        let query = conf.first_by_id_query(group, id);
+
+        self.blocking(move |s| s.select_first(vec![query]))
+    }
+    pub fn list_with_limit_by_id<'c, 'a, T: Sync>(&self, conf: &'c Conf<'a, T>, group: i64, last_item_id: Option<i64>) -> Pending<'c, Vec<HashMap<String, Column>>> {
+        self.select_live(last_item_id, move |last| Ok(conf.list_with_limit_by_id_queries(group, last)), vec![])
+    }
+    pub fn list_with_limit_by_id_with_deleted<'c, 'a, T: Sync>(&self, conf: &'c Conf<'a, T>, group: i64, last_item_id: Option<i64>) -> Pending<'static, Vec<HashMap<String, Column>>> {
+        let queries = conf.list_with_limit_by_id_queries(group, last_item_id);
+
+        self.blocking(move |s| s.select_all(queries))
+    }
+    // same as the sync select_live, one page per round trip to the blocking pool
+    pub fn select_live<'c, Q>(&self, last_item_id: Option<i64>, queries: Q, res: Vec<HashMap<String, Column>>) -> Pending<'c, Vec<HashMap<String, Column>>>
+        where Q: Fn(Option<i64>) -> Result<Vec<(String, Vec<Column>)>> + Send + 'c {
+
+        let this = self.clone();
+        let page = match queries(last_item_id) {
+            Ok(page) => page,
+            Err(e) => return future::err(e).boxed()
+        };
+
+        self.blocking(move |s| s.select_all(page)).and_then(move |rows| {
+            let mut res = res;
+
+            match keep_live(&mut res, rows) {
+                Some(id) => this.select_live(Some(id), queries, res),
+                None => {
+                    res.truncate(10);
+                    future::ok(res).boxed()
+                }
+            }
+        }).boxed()
+    }
+    // rows created in [from, to), newest first, ids must be simpleflake ids
+    pub fn list_by_time<'c, 'a, T: Sync>(&self, conf: &'c Conf<'a, T>, group: i64, from: DateTime<UTC>, to: DateTime<UTC>, last_item_id: Option<i64>) -> Pending<'c, Vec<HashMap<String, Column>>> {
+        self.select_live(last_item_id, move |last| conf.list_by_time_queries(group, &from, &to, last), vec![])
+    }
+    pub fn list_by_time_with_deleted<'c, 'a, T: Sync>(&self, conf: &'c Conf<'a, T>, group: i64, from: DateTime<UTC>, to: DateTime<UTC>, last_item_id: Option<i64>) -> Pending<'static, Vec<HashMap<String, Column>>> {
+        let queries = match conf.list_by_time_queries(group, &from, &to, last_item_id) {
+            Ok(queries) => queries,
+            Err(e) => return future::err(e).boxed()
+        };
+
+        self.blocking(move |s| s.select_all(queries))
+    }
+    pub fn list_by_field<'c, 'a, T: Sync>(&self, conf: &'c Conf<'a, T>, group: i64, fc: &FieldConf, last: Option<(Column, i64)>) -> Pending<'static, Vec<HashMap<String, Column>>> {
+        let queries = conf.list_by_field_queries(group, fc, last);
+
+        self.blocking(move |s| s.select_all(queries))
+    }
+    pub fn execute_planned(&self, batch: Vec<BatchQuery>, consistency: Consistency, mode: BatchMode, idempotent: bool) -> Pending<'static, Response> {
+        self.blocking(move |s| s.execute_planned(batch, consistency, &mode, idempotent))
+    }
+    pub fn execute_with_counters(&self, batch: Vec<BatchQuery>, counters: Vec<CounterUpdate>, consistency: Consistency, mode: BatchMode, idempotent: bool) -> Pending<'static, Response> {
+        self.blocking(move |s| s.execute_with_counters(batch, counters, consistency, &mode, idempotent))
+    }
+    pub fn insert_all<'c, 'a, T: Sync>(&self, conf: &'c Conf<'a, T>, group: i64, id: i64, values: Vec<Column>, consistency: Consistency, opts: &WriteOptions) -> Pending<'c, Response> {
+        let opts = conf.get_write_options(opts);
+        let this = self.clone();
+
+        if let Err(e) = conf.check_id(id) {
+            return future::err(e.into()).boxed();
+        }
+
+        let mut values = values;
+
+        conf.fill_all(&mut values);
+
+        let mut values = match conf.check_all_values(values, &opts) {
+            Ok(values) => values,
+            Err(e) => return future::err(e.into()).boxed()
+        };
+
+        self.first_by_id_with_deleted(conf, group, id).and_then(move |state| {
+            let state = conf.revive_all(state, &mut values);
+
+            let counters = conf.counters_for_all(group, &state, &values);
+
+            let batch = conf.get_batch_for_insert_all(group, id, values, state, &opts);
+
+            this.execute_with_counters(batch, counters, consistency, opts.batch, true)
+        }).boxed()
+    }
+    pub fn insert<'c, 'a, T: Sync>(&self, conf: &'c Conf<'a, T>, group: i64, id: i64, f_v: Vec<(&'c FieldConf<'c>, Column)>, consistency: Consistency, opts: &WriteOptions) -> Pending<'c, Response> {
+        let opts = conf.get_write_options(opts);
+        let this = self.clone();
+
+        if let Err(e) = conf.check_id(id) {
+            return future::err(e.into()).boxed();
+        }
+
+        self.first_by_id_with_deleted(conf, group, id).and_then(move |state| {
+            let (mut f, values): (Vec<&FieldConf>, Vec<Column>) = f_v.into_iter().unzip();
+
+            let mut values = match conf.check_values(&f, values, &opts) {
+                Ok(values) => values,
+                Err(e) => return future::err(e.into()).boxed()
+            };
+
+            conf.fill(&mut f, &mut values, &[], &state);
+
+            let state = conf.revive(state, &mut f, &mut values, &[]);
+
+            let counters = conf.counters_for(group, &state, &f, &values);
+
+            let batch = conf.get_batch_for_insert(group, id, f, values, state, &opts);
+
+            this.execute_with_counters(batch, counters, consistency, opts.batch, true)
+        }).boxed()
+    }
+    pub fn insert_hashmap<'c, 'a, T: Sync>(&self, conf: &'c Conf<'a, T>, group: i64, id: i64, hashmap: HashMap<String, Column>, extra_fields: Option<Vec<(&'c FieldConf<'c>, Column)>>, consistency: Consistency, opts: &WriteOptions) -> Pending<'c, Response> {
+        let opts = conf.get_write_options(opts);
+        let this = self.clone();
+
+        if let Err(e) = conf.check_id(id) {
+            return future::err(e.into()).boxed();
+        }
+
+        self.first_by_id_with_deleted(conf, group, id).and_then(move |state| {
+            let mut f: Vec<&FieldConf> = vec![];
+            let mut values: Vec<Column> = vec![];
+
+            conf.hashmap_fields(hashmap, &mut f, &mut values);
+
+            if let Some(ef) = extra_fields {
+                for (fc, v) in ef.into_iter() {
+                    f.push(fc);
+                    values.push(v);
+                }
+            }
+
+            let mut values = match conf.check_values(&f, values, &opts) {
+                Ok(values) => values,
+                Err(e) => return future::err(e.into()).boxed()
+            };
+
+            conf.fill(&mut f, &mut values, &[], &state);
+
+            let state = conf.revive(state, &mut f, &mut values, &[]);
+
+            let counters = conf.counters_for(group, &state, &f, &values);
+
+            let batch = conf.get_batch_for_insert(group, id, f, values, state, &opts);
+
+            this.execute_with_counters(batch, counters, consistency, opts.batch, true)
+        }).boxed()
+    }
+    // Set writes the value, Null clears it and drops its index rows, Unchanged fields are left out
+    pub fn update<'c, 'a, T: Sync>(&self, conf: &'c Conf<'a, T>, group: i64, id: i64, f_v: Vec<(&'c FieldConf<'c>, FieldValue)>, consistency: Consistency, opts: &WriteOptions) -> Pending<'c, Response> {
+        let opts = conf.get_write_options(opts);
+        let this = self.clone();
+
+        if let Err(e) = conf.check_id(id) {
+            return future::err(e.into()).boxed();
+        }
+
+        let (f, values, nulls) = match conf.update_values(f_v, &opts) {
+            Ok(x) => x,
+            Err(e) => return future::err(e.into()).boxed()
+        };
+
+        self.first_by_id_with_deleted(conf, group, id).and_then(move |state| {
+            let (batch, counters) = conf.update_jobs(group, id, f, values, nulls, state, &opts);
+
+            this.execute_with_counters(batch, counters, consistency, opts.batch, true)
+        }).boxed()
+    }
+    pub fn append<'c, 'a, T: Sync>(&self, conf: &'c Conf<'a, T>, group: i64, id: i64, fc: &'c FieldConf<'c>, items: Column, consistency: Consistency, opts: &WriteOptions) -> Pending<'c, Response> {
+        let opts = conf.get_write_options(opts);
+        let this = self.clone();
+
+        if let Err(e) = conf.check_id(id) {
+            return future::err(e.into()).boxed();
+        }
+
+        let state = if conf.has_index_rows() || conf.counters {
+            self.first_by_id_with_deleted(conf, group, id)
+        } else {
+            future::ready(Ok(None)).boxed()
+        };
+
+        state.and_then(move |state| {
+            let counters = conf.collection_counters(group, fc, &state, &items, true);
+
+            let batch = conf.get_batch_for_append(group, id, fc, items, state, &opts);
+
+            this.execute_with_counters(batch, counters, consistency, opts.batch, is_idempotent_append(fc))
+        }).boxed()
+    }
+    pub fn remove<'c, 'a, T: Sync>(&self, conf: &'c Conf<'a, T>, group: i64, id: i64, fc: &'c FieldConf<'c>, items: Column, consistency: Consistency, opts: &WriteOptions) -> Pending<'c, Response> {
+        let opts = conf.get_write_options(opts);
+        let this = self.clone();
+
+        let state = if conf.has_index_rows() || conf.counters {
+            self.first_by_id_with_deleted(conf, group, id)
+        } else {
+            future::ready(Ok(None)).boxed()
+        };
+
+        state.and_then(move |state| {
+            let counters = conf.collection_counters(group, fc, &state, &items, false);
+
+            let batch = conf.get_batch_for_remove(group, id, fc, items, state, &opts);
+
+            this.execute_with_counters(batch, counters, consistency, opts.batch, true)
+        }).boxed()
+    }
+    pub fn delete<'c, 'a, T: Sync>(&self, conf: &'c Conf<'a, T>, group: i64, id: i64, consistency: Consistency, opts: &WriteOptions) -> Pending<'c, Response> {
+        let opts = conf.get_write_options(opts);
+        let this = self.clone();
+
+        self.first_by_id(conf, group, id).and_then(move |state| {
+            if conf.soft_delete && state.is_none() {
+                return future::err(Error::new(ErrorKind::NotFound, "no row to delete")).boxed();
+            }
+
+            let counters = conf.count_jobs(group, state.as_ref(), None);
+
+            let batch = conf.get_batch_for_delete(group, id, state, &opts);
+
+            this.execute_with_counters(batch, counters, consistency, opts.batch, true)
+        }).boxed()
+    }
+    pub fn purge<'c, 'a, T: Sync>(&self, conf: &'c Conf<'a, T>, group: i64, id: i64, consistency: Consistency, opts: &WriteOptions) -> Pending<'c, Response> {
+        let opts = conf.get_write_options(opts);
+        let this = self.clone();
+
+        self.first_by_id_with_deleted(conf, group, id).and_then(move |state| {
+            let counters = match state {
+                Some(ref row) if !is_deleted(row) => conf.count_jobs(group, Some(row), None),
+                _ => vec![]
+            };
+
+            let batch = conf.get_batch_for_purge(group, id, state, &opts);
+
+            this.execute_with_counters(batch, counters, consistency, opts.batch, true)
+        }).boxed()
+    }
+    pub fn restore<'c, 'a, T: Sync>(&self, conf: &'c Conf<'a, T>, group: i64, id: i64, consistency: Consistency, opts: &WriteOptions) -> Pending<'c, Response> {
+        let opts = conf.get_write_options(opts);
+        let this = self.clone();
+
+        self.first_by_id_with_deleted(conf, group, id).and_then(move |state| {
+            let row = match state {
+                Some(row) if is_deleted(&row) => row,
+                _ => return future::err(Error::new(ErrorKind::NotFound, "no deleted row to restore")).boxed()
+            };
+
+            let counters = conf.count_jobs(group, None, Some(&row));
+
+            let batch = conf.get_batch_for_restore(group, id, row, &opts);
+
+            this.execute_with_counters(batch, counters, consistency, opts.batch, true)
+        }).boxed()
+    }
+    pub fn increment(&self, cc: &CounterConf, group: i64, id: i64, counter: &str, by: i64, consistency: Consistency) -> Pending<'static, Response> {
+        self.add(cc, group, id, vec![(counter, by)], consistency)
+    }
+    pub fn decrement(&self, cc: &CounterConf, group: i64, id: i64, counter: &str, by: i64, consistency: Consistency) -> Pending<'static, Response> {
+        match negate(by) {
+            Ok(by) => self.add(cc, group, id, vec![(counter, by)], consistency),
+            Err(e) => future::err(e).boxed()
+        }
+    }
+    pub fn add(&self, cc: &CounterConf, group: i64, id: i64, deltas: Vec<(&str, i64)>, consistency: Consistency) -> Pending<'static, Response> {
+        let update = match cc.get_update(group, id, deltas) {
+            Ok(update) => update,
+            Err(e) => return future::err(e).boxed()
+        };
+
+        self.blocking(move |s| s.execute_counters(vec![update], consistency))
+    }
+    // None when no counter of the id was ever updated, counters never updated read as 0
+    pub fn read_counters(&self, cc: &CounterConf, group: i64, id: i64) -> Pending<'static, Option<HashMap<String, i64>>> {
+        let query = cc.read_query(group, id);
+        let counters = cc.counters.iter().map(|x| x.to_string()).collect::<Vec<String>>();
+
+        self.blocking(move |s| s.select_first(vec![query])).map_ok(move |row| row.map(|row| counters.into_iter().map(|x| {
+            let v = match row.get(&x) {
+                Some(&Column::Bigint(v)) => v,
+                _ => 0
+            };
+
+            (x, v)
+        }).collect())).boxed()
+    }
+}
@@ -0,0 +1,163 @@
+// table options rendered into the with clause of every generated create table,
+// a Conf carries options for all its tables and overrides per generated table name
+
+pub enum WindowUnit {
+    Minutes,
+    Hours,
+    Days
+}
+
+pub enum Compaction {
+    SizeTiered { min_threshold: Option<u32>, max_threshold: Option<u32> },
+    Leveled { sstable_size_in_mb: Option<u32> },
+    TimeWindow { unit: WindowUnit, size: u32 }
+}
+
+pub enum RowCache {
+    None,
+    All,
+    PerPartition(u32)
+}
+
+pub struct Caching {
+    pub keys: bool,
+    pub rows: RowCache
+}
+
+// chunk_length_in_kb
+pub enum Compression {
+    Lz4(Option<u32>),
+    Snappy(Option<u32>),
+    Deflate(Option<u32>),
+    Zstd(Option<u32>),
+    Disabled
+}
+
+pub struct TableOptions {
+    pub compaction: Option<Compaction>,
+    pub default_time_to_live: Option<u32>,
+    pub gc_grace_seconds: Option<u32>,
+    pub caching: Option<Caching>,
+    pub compression: Option<Compression>,
+    pub bloom_filter_fp_chance: Option<f64>
+}
+
+pub static GC_GRACE_SECONDS: u32 = 86400;
+
+pub fn new_table_options() -> TableOptions {
+    TableOptions {
+        compaction: None,
+        default_time_to_live: None,
+        gc_grace_seconds: None,
+        caching: None,
+        compression: None,
+        bloom_filter_fp_chance: None
+    }
+}
+
+impl Compaction {
+    pub fn render(&self) -> String {
+        match self {
+            &Compaction::SizeTiered { min_threshold, max_threshold } => {
+                "{'class': 'SizeTieredCompactionStrategy'".to_string()
+                + &opt_param("min_threshold", min_threshold)
+                + &opt_param("max_threshold", max_threshold) + "}"
+            }
+            &Compaction::Leveled { sstable_size_in_mb } => {
+                "{'class': 'LeveledCompactionStrategy'".to_string()
+                + &opt_param("sstable_size_in_mb", sstable_size_in_mb) + "}"
+            }
+            &Compaction::TimeWindow { ref unit, size } => {
+                let unit = match unit {
+                    &WindowUnit::Minutes => "MINUTES",
+                    &WindowUnit::Hours => "HOURS",
+                    &WindowUnit::Days => "DAYS"
+                };
+
+                "{'class': 'TimeWindowCompactionStrategy', 'compaction_window_unit': '".to_string()
+                + unit + "', 'compaction_window_size': '" + &size.to_string() + "'}"
+            }
+        }
+    }
+}
+
+impl Caching {
+    pub fn render(&self) -> String {
+        let keys = if self.keys { "ALL" } else { "NONE" };
+
+        let rows = match self.rows {
+            RowCache::None => "NONE".to_string(),
+            RowCache::All => "ALL".to_string(),
+            RowCache::PerPartition(n) => n.to_string()
+        };
+
+        "{'keys': '".to_string() + keys + "', 'rows_per_partition': '" + &rows + "'}"
+    }
+}
+
+impl Compression {
+    pub fn render(&self) -> String {
+        let (class, chunk) = match self {
+            &Compression::Lz4(chunk) => ("LZ4Compressor", chunk),
+            &Compression::Snappy(chunk) => ("SnappyCompressor", chunk),
+            &Compression::Deflate(chunk) => ("DeflateCompressor", chunk),
+            &Compression::Zstd(chunk) => ("ZstdCompressor", chunk),
+            &Compression::Disabled => return "{'enabled': 'false'}".to_string()
+        };
+
+        "{'class': '".to_string() + class + "'" + &opt_param("chunk_length_in_kb", chunk) + "}"
+    }
+}
+
+impl TableOptions {
+    // options set here win over the ones in base
+    pub fn render_over(&self, base: &TableOptions) -> String {
+        self.render_options(base, true)
+    }
+    pub fn render(&self) -> String {
+        self.render_over(&new_table_options())
+    }
+    // counter tables take no default_time_to_live
+    pub fn render_counter_over(&self, base: &TableOptions) -> String {
+        self.render_options(base, false)
+    }
+    pub fn render_counter(&self) -> String {
+        self.render_counter_over(&new_table_options())
+    }
+    fn render_options(&self, base: &TableOptions, ttl: bool) -> String {
+        let mut res = vec![];
+
+        let gc_grace_seconds = self.gc_grace_seconds.or(base.gc_grace_seconds).unwrap_or(GC_GRACE_SECONDS);
+
+        res.push("gc_grace_seconds = ".to_string() + &gc_grace_seconds.to_string());
+
+        if let Some(c) = self.compaction.as_ref().or(base.compaction.as_ref()) {
+            res.push("compaction = ".to_string() + &c.render());
+        }
+
+        if let Some(ttl) = self.default_time_to_live.or(base.default_time_to_live).filter(|_| ttl) {
+            res.push("default_time_to_live = ".to_string() + &ttl.to_string());
+        }
+
+        if let Some(c) = self.caching.as_ref().or(base.caching.as_ref()) {
+            res.push("caching = ".to_string() + &c.render());
+        }
+
+        if let Some(c) = self.compression.as_ref().or(base.compression.as_ref()) {
+            res.push("compression = ".to_string() + &c.render());
+        }
+
+        if let Some(chance) = self.bloom_filter_fp_chance.or(base.bloom_filter_fp_chance) {
+            res.push("bloom_filter_fp_chance = ".to_string() + &chance.to_string());
+        }
+
+        res.join(" and ")
+    }
+}
+
+fn opt_param(name: &str, value: Option<u32>) -> String {
+    match value {
+        Some(v) => ", '".to_string() + name + "': '" + &v.to_string() + "'",
+        None => "".to_string()
+    }
+}
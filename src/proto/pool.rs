@@ -0,0 +1,174 @@
+use std::io::Result;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::time::Duration;
+use std::time::Instant;
+
+use proto::session::*;
+use proto::error::server_error_of;
+
+
+// a contact point and its open sessions, a host without sessions is down
+pub struct Host<S = Session> {
+    pub addr: String,
+    pub sessions: Vec<S>,
+    pub down_since: Option<Instant>,
+    next: usize
+}
+
+pub struct Pool<S = Session> {
+    pub hosts: Vec<Host<S>>,
+    pub per_host: usize,
+    // how long a down host is left alone before the health check reconnects it
+    pub reconnect_after: Duration,
+    next: usize,
+    connector: fn(String) -> Result<S>
+}
+
+pub fn new_pool(hosts: Vec<&str>, per_host: usize) -> Pool {
+    new_pool_with(hosts, per_host, open)
+}
+
+pub fn new_pool_with<S>(hosts: Vec<&str>, per_host: usize, connector: fn(String) -> Result<S>) -> Pool<S> {
+    let mut pool = Pool {
+        hosts: hosts.iter().map(|addr| Host {
+            addr: addr.to_string(),
+            sessions: vec![],
+            down_since: None,
+            next: 0
+        }).collect(),
+        per_host: per_host,
+        reconnect_after: Duration::from_secs(10),
+        next: 0,
+        connector: connector
+    };
+
+    for i in 0..pool.hosts.len() {
+        pool.connect(i);
+    }
+
+    pool
+}
+
+impl<S> Pool<S> {
+    pub fn is_up(&self, i: usize) -> bool {
+        self.hosts[i].down_since.is_none()
+    }
+    pub fn mark_down(&mut self, i: usize) {
+        let host = &mut self.hosts[i];
+
+        host.sessions.clear();
+        host.down_since = Some(Instant::now());
+    }
+    // reconnects down hosts that were left alone long enough
+    pub fn check(&mut self) {
+        for i in 0..self.hosts.len() {
+            let due = match self.hosts[i].down_since {
+                Some(since) => since.elapsed() >= self.reconnect_after,
+                None => false
+            };
+
+            if due {
+                self.connect(i);
+            }
+        }
+    }
+    // runs f on a session of the next host that is up, connection errors mark the host down,
+    // f runs again on another host only when the request never reached the server
+    pub fn run<R, F>(&mut self, f: F) -> Result<R> where F: FnMut(&mut S) -> Result<R> {
+        self.run_on(f, false)
+    }
+    // same as run, f is safe to repeat so it runs again on another host after any connection error
+    pub fn run_idempotent<R, F>(&mut self, f: F) -> Result<R> where F: FnMut(&mut S) -> Result<R> {
+        self.run_on(f, true)
+    }
+    fn run_on<R, F>(&mut self, mut f: F, idempotent: bool) -> Result<R> where F: FnMut(&mut S) -> Result<R> {
+        self.check();
+
+        let mut last = None;
+
+        for _ in 0..self.hosts.len() {
+            let i = self.next % self.hosts.len();
+
+            self.next = self.next + 1;
+
+            if !self.is_up(i) || self.hosts[i].sessions.len() == 0 {
+                continue;
+            }
+
+            let result = {
+                let host = &mut self.hosts[i];
+                let j = host.next % host.sessions.len();
+
+                host.next = host.next + 1;
+
+                f(&mut host.sessions[j])
+            };
+
+            match result {
+                Ok(r) => return Ok(r),
+                Err(e) => {
+                    if !is_connection_error(&e) {
+                        return Err(e)
+                    }
+
+                    self.mark_down(i);
+
+                    if !idempotent && !is_never_sent(&e) {
+                        return Err(e)
+                    }
+
+                    last = Some(e);
+                }
+            }
+        }
+
+        Err(last.unwrap_or(Error::new(ErrorKind::NotConnected, "all hosts are down")))
+    }
+    fn connect(&mut self, i: usize) {
+        let mut sessions = vec![];
+
+        for _ in 0..self.per_host {
+            match (self.connector)(self.hosts[i].addr.clone()) {
+                Ok(s) => sessions.push(s),
+                Err(e) => {
+                    //println!("can't connect to {}: {:?}", self.hosts[i].addr, e);
+                    self.mark_down(i);
+                    return
+                }
+            }
+        }
+
+        let host = &mut self.hosts[i];
+
+        host.sessions = sessions;
+        host.down_since = None;
+    }
+}
+
+// only transport errors say something about the host, errors the server answers with
+// (timeouts, unavailable, invalid queries) and errors of the request itself don't
+pub fn is_connection_error(e: &Error) -> bool {
+    if server_error_of(e).is_some() {
+        return false
+    }
+
+    match e.kind() {
+        ErrorKind::ConnectionReset
+        | ErrorKind::ConnectionAborted
+        | ErrorKind::BrokenPipe
+        | ErrorKind::ConnectionRefused
+        | ErrorKind::NotConnected
+        | ErrorKind::UnexpectedEof
+        | ErrorKind::TimedOut => true,
+        _ => false
+    }
+}
+
+// the connection was not there, the request can't have reached the server
+pub fn is_never_sent(e: &Error) -> bool {
+    match e.kind() {
+        ErrorKind::ConnectionRefused | ErrorKind::NotConnected => server_error_of(e).is_none(),
+        _ => false
+    }
+}
@@ -0,0 +1,107 @@
+use std::io::Result;
+use std::thread;
+use std::time::Duration;
+
+use rustcql::shared::Consistency;
+use rustcql::shared::Response;
+use rustcql::shared::Column;
+
+use proto::session::Session;
+use proto::error::server_error;
+
+
+// error codes of the native protocol worth another attempt
+pub static UNAVAILABLE: i32 = 0x1000;
+pub static OVERLOADED: i32 = 0x1001;
+pub static IS_BOOTSTRAPPING: i32 = 0x1002;
+pub static WRITE_TIMEOUT: i32 = 0x1100;
+pub static READ_TIMEOUT: i32 = 0x1200;
+
+// only idempotent statements are retried: reads, and writes stamped with a client timestamp,
+// which every generated insert, delete and set or map update is. list appends and counters are not
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub on_read_timeout: bool,
+    pub on_write_timeout: bool,
+    pub on_unavailable: bool,
+    pub on_overloaded: bool
+}
+
+pub fn new_retry_policy() -> RetryPolicy {
+    RetryPolicy {
+        max_attempts: 3,
+        base_delay: Duration::from_millis(100),
+        max_delay: Duration::from_secs(2),
+        on_read_timeout: true,
+        on_write_timeout: true,
+        on_unavailable: true,
+        on_overloaded: true
+    }
+}
+
+pub fn no_retry() -> RetryPolicy {
+    RetryPolicy {
+        max_attempts: 1,
+        .. new_retry_policy()
+    }
+}
+
+impl RetryPolicy {
+    pub fn should_retry(&self, response: &Response) -> bool {
+        match response {
+            &Response::Error(code, _) => {
+                (code == READ_TIMEOUT && self.on_read_timeout)
+                || (code == WRITE_TIMEOUT && self.on_write_timeout)
+                || ((code == UNAVAILABLE || code == IS_BOOTSTRAPPING) && self.on_unavailable)
+                || (code == OVERLOADED && self.on_overloaded)
+            }
+            _ => false
+        }
+    }
+    // exponential backoff, attempt starts at 1
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let mut delay = self.base_delay;
+
+        for _ in 1..attempt {
+            delay = delay * 2;
+
+            if delay >= self.max_delay {
+                return self.max_delay
+            }
+        }
+
+        delay
+    }
+}
+
+impl Session {
+    pub fn retrying<F>(&mut self, idempotent: bool, mut f: F) -> Result<Response> where F: FnMut(&mut Session) -> Result<Response> {
+        let mut attempt = 0;
+
+        loop {
+            let result = try!(f(self));
+
+            attempt = attempt + 1;
+
+            if !idempotent || attempt >= self.retry.max_attempts || !self.retry.should_retry(&result) {
+                return Ok(result)
+            }
+
+            thread::sleep(self.retry.delay(attempt));
+        }
+    }
+    // retried prm_query, errors the server answers with come back as Err with a ServerError
+    pub fn read(&mut self, query: String, values: Vec<Column>, consistency: Consistency) -> Result<Response> {
+        answered(try!(self.retrying(true, |s| s.prm_query(query.clone(), values.clone(), consistency.clone()))))
+    }
+}
+
+// the answer of the server, an error answer as Err with a ServerError
+pub fn answered(result: Response) -> Result<Response> {
+    match result {
+        Response::Error(code, message) => Err(server_error(code, message)),
+        result => Ok(result)
+    }
+}
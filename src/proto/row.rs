@@ -0,0 +1,64 @@
+use std::any::type_name;
+use std::collections::HashMap;
+use std::result;
+
+use rustcql::shared::Column;
+
+use proto::shared::*;
+use proto::value::*;
+use proto::error::Error;
+
+
+// rows decoded from the columns a select returns
+// tuples take the fields of the Conf in order, (group, id) are not part of them
+
+pub trait FromRow: Sized {
+    fn from_row(row: &HashMap<String, Column>, fields: &[FieldConf]) -> result::Result<Self, Error>;
+}
+
+impl FromRow for HashMap<String, Column> {
+    fn from_row(row: &HashMap<String, Column>, fields: &[FieldConf]) -> result::Result<HashMap<String, Column>, Error> {
+        Ok(row.clone())
+    }
+}
+
+pub fn column<T: FromColumn>(row: &HashMap<String, Column>, name: &str) -> result::Result<T, Error> {
+    match row.get(name) {
+        Some(c) => T::from_column(c).ok_or_else(|| Error::Type(name.to_string(), type_name::<T>())),
+        None => Err(Error::Missing(name.to_string()))
+    }
+}
+
+// for i64 fields stored as timestamps
+pub fn timestamp_column(row: &HashMap<String, Column>, name: &str) -> result::Result<i64, Error> {
+    match row.get(name) {
+        Some(c) => timestamp_of(c).ok_or_else(|| Error::Type(name.to_string(), "timestamp")),
+        None => Err(Error::Missing(name.to_string()))
+    }
+}
+
+fn nth_column<T: FromColumn>(row: &HashMap<String, Column>, fields: &[FieldConf], i: usize) -> result::Result<T, Error> {
+    match fields.get(i) {
+        Some(fc) => column(row, fc.f.get_name()),
+        None => Err(Error::Missing(format!("#{}", i)))
+    }
+}
+
+macro_rules! tuple_from_row {
+    ($($t:ident $i:tt),+) => {
+        impl<$($t: FromColumn),+> FromRow for ($($t,)+) {
+            fn from_row(row: &HashMap<String, Column>, fields: &[FieldConf]) -> result::Result<($($t,)+), Error> {
+                Ok(($(try!(nth_column::<$t>(row, fields, $i)),)+))
+            }
+        }
+    }
+}
+
+tuple_from_row!(A 0);
+tuple_from_row!(A 0, B 1);
+tuple_from_row!(A 0, B 1, C 2);
+tuple_from_row!(A 0, B 1, C 2, D 3);
+tuple_from_row!(A 0, B 1, C 2, D 3, E 4);
+tuple_from_row!(A 0, B 1, C 2, D 3, E 4, F 5);
+tuple_from_row!(A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+tuple_from_row!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
@@ -11,6 +11,8 @@ use rustcql::shared::Row;
 use rustcql::shared::BatchQuery;
 use rustcql::shared::Column;
 
+use proto::session::Session;
+
 
 // Rules:
 // fields with QueryType Storaged, Value, Substring must form a set without duplicates (check not implemented)
@@ -26,7 +28,7 @@ pub struct Schema {
     pub queries: Vec<String>
 }
 
-pub fn create_schema(conn: &mut Connection, context: Vec<Schema>, replication_factor: u32) {
+pub fn create_schema(conn: &mut Session, context: Vec<Schema>, replication_factor: u32) {
     let result = conn.query("DROP KEYSPACE IF EXISTS test1".to_string(), Consistency::Quorum);
     println!("Result of DROP KEYSPACE was {:?}", result);
 
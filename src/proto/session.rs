@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use std::io::Result;
+use std::io::Error;
+use std::io::ErrorKind;
+
+use rustcql::Connection;
+use rustcql::connect;
+use rustcql::shared::Consistency;
+use rustcql::shared::Response;
+use rustcql::shared::ResultBody;
+use rustcql::shared::BatchQuery;
+use rustcql::shared::Column;
+
+use proto::retry::*;
+use proto::error::server_error;
+
+
+// error code the server answers with when it doesn't know a prepared id, after a restart for example
+pub static UNPREPARED: i32 = 0x2500;
+
+// prepared statements a session keeps, the least recently used goes first above it
+pub static PREPARED_LIMIT: usize = 1024;
+
+// a connection with its prepared statements, keyed by the generated cql text
+pub struct Session {
+    pub conn: Connection,
+    pub prepared: PreparedCache,
+    pub retry: RetryPolicy
+}
+
+pub fn new_session(conn: Connection) -> Session {
+    Session {
+        conn: conn,
+        prepared: new_prepared_cache(PREPARED_LIMIT),
+        retry: new_retry_policy()
+    }
+}
+
+// prepared ids by cql text with the tick of their last use
+pub struct PreparedCache {
+    pub limit: usize,
+    entries: HashMap<String, (Vec<u8>, u64)>,
+    tick: u64
+}
+
+pub fn new_prepared_cache(limit: usize) -> PreparedCache {
+    PreparedCache {
+        limit: limit,
+        entries: HashMap::new(),
+        tick: 0
+    }
+}
+
+impl PreparedCache {
+    pub fn get(&mut self, query: &str) -> Option<Vec<u8>> {
+        self.tick += 1;
+
+        let tick = self.tick;
+
+        self.entries.get_mut(query).map(|entry| {
+            entry.1 = tick;
+            entry.0.clone()
+        })
+    }
+    pub fn insert(&mut self, query: String, id: Vec<u8>) {
+        self.tick += 1;
+
+        if !self.entries.contains_key(&query) && self.entries.len() >= self.limit {
+            let oldest = self.entries.iter().min_by_key(|&(_, &(_, tick))| tick).map(|(k, _)| k.clone());
+
+            if let Some(k) = oldest {
+                self.entries.remove(&k);
+            }
+        }
+
+        self.entries.insert(query, (id, self.tick));
+    }
+    pub fn remove(&mut self, query: &str) {
+        self.entries.remove(query);
+    }
+    pub fn contains(&self, query: &str) -> bool {
+        self.entries.contains_key(query)
+    }
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+pub fn open(host: String) -> Result<Session> {
+    let conn = try!(connect(host));
+
+    Ok(new_session(conn))
+}
+
+impl Session {
+    // statements that run once, like schema changes, are not prepared
+    pub fn query(&mut self, query: String, consistency: Consistency) -> Result<Response> {
+        self.conn.query(query, consistency)
+    }
+    pub fn prepare(&mut self, query: &str) -> Result<Vec<u8>> {
+        if let Some(id) = self.prepared.get(query) {
+            return Ok(id)
+        }
+
+        let result = try!(self.conn.prepare(query.to_string()));
+
+        match result {
+            Response::Result(ResultBody::Prepared(id)) => {
+                self.prepared.insert(query.to_string(), id.clone());
+                Ok(id)
+            }
+            Response::Error(code, message) => Err(server_error(code, message)),
+            result => Err(Error::new(ErrorKind::InvalidData, format!("can't prepare {}: {:?}", query, result)))
+        }
+    }
+    pub fn prm_query(&mut self, query: String, values: Vec<Column>, consistency: Consistency) -> Result<Response> {
+        let id = try!(self.prepare(&query));
+
+        let result = try!(self.conn.execute(id, values.clone(), consistency.clone()));
+
+        if !is_unprepared(&result) {
+            return Ok(result)
+        }
+
+        self.prepared.remove(&query);
+
+        let id = try!(self.prepare(&query));
+
+        self.conn.execute(id, values, consistency)
+    }
+    pub fn execute_batch(&mut self, batch: Vec<BatchQuery>, consistency: Consistency) -> Result<Response> {
+        self.execute_batch_ref(&batch, consistency)
+    }
+    pub fn execute_batch_ref(&mut self, batch: &Vec<BatchQuery>, consistency: Consistency) -> Result<Response> {
+        let prepared = try!(self.prepare_batch(batch));
+
+        let result = try!(self.conn.execute_batch(prepared, consistency.clone()));
+
+        if !is_unprepared(&result) {
+            return Ok(result)
+        }
+
+        for bq in batch.iter() {
+            if let &BatchQuery::SimpleWithParams(ref query, _) = bq {
+                self.prepared.remove(query);
+            }
+        }
+
+        let prepared = try!(self.prepare_batch(batch));
+
+        self.conn.execute_batch(prepared, consistency)
+    }
+    pub fn prepare_batch(&mut self, batch: &Vec<BatchQuery>) -> Result<Vec<BatchQuery>> {
+        let mut res = vec![];
+
+        for bq in batch.iter() {
+            match bq {
+                &BatchQuery::SimpleWithParams(ref query, ref values) => {
+                    let id = try!(self.prepare(query));
+                    res.push(BatchQuery::Prepared(id, values.clone()));
+                }
+                &BatchQuery::Prepared(ref id, ref values) => {
+                    res.push(BatchQuery::Prepared(id.clone(), values.clone()));
+                }
+                _ => panic!("only queries with params can be prepared")
+            }
+        }
+
+        Ok(res)
+    }
+}
+
+pub fn is_unprepared(response: &Response) -> bool {
+    match response {
+        &Response::Error(code, _) => code == UNPREPARED,
+        _ => false
+    }
+}
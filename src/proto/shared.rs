@@ -1,9 +1,16 @@
+use std::collections::HashMap;
+use std::sync::atomic::AtomicI64;
+use std::sync::atomic::Ordering;
+
 use chrono::*;
 use rustcql::shared::Column;
 
+use proto::batch::*;
+
 // Rules:
 // fields with QueryType Storaged, Value, Substring must form a set without duplicates (check not implemented)
 // fields with QueryType Substring must be Field::Text(_) only (check not implemented)
+// fields with QueryType Value or Substring must not be Field::List(_) or Field::Map(_) (check not implemented)
 
 // (group id) f1 f2 ... fn                                  main
 // (group f1 id) f2 ... fn                                  Value
@@ -21,28 +28,262 @@ pub fn now() -> Column {
 }
 
 
+// write timestamps are in microseconds
+pub fn now_micros() -> i64 {
+    let t = UTC::now();
+
+    t.timestamp() * 1000000 + (t.nanosecond() / 1000) as i64
+}
+
+// hybrid clock, follows the wall clock but never hands out the same or a smaller timestamp twice
+pub struct Clock {
+    last: AtomicI64
+}
+
+pub const fn new_clock() -> Clock {
+    Clock {
+        last: AtomicI64::new(0)
+    }
+}
+
+impl Clock {
+    pub fn next(&self) -> i64 {
+        let t = now_micros();
+
+        let mut last = self.last.load(Ordering::SeqCst);
+
+        loop {
+            let next = if t > last { t } else { last + 1 };
+
+            match self.last.compare_exchange(last, next, Ordering::SeqCst, Ordering::SeqCst) {
+                Ok(_) => return next,
+                Err(x) => last = x
+            }
+        }
+    }
+}
+
+// write timestamps the api derives come from one clock per process, two writes of the process never tie
+pub static WRITE_CLOCK: Clock = new_clock();
+
+pub fn next_micros() -> i64 {
+    WRITE_CLOCK.next()
+}
+
+// options applied to every statement of a write, main row and index rows alike
+pub struct WriteOptions {
+    pub ttl: Option<u32>,
+    pub timestamp: Option<i64>,
+    pub batch: BatchMode,
+    // convert values to the type of their field where it is lossless enough, see check_column
+    pub coerce: bool
+}
+
+pub fn new_write_options() -> WriteOptions {
+    WriteOptions {
+        ttl: None,
+        timestamp: None,
+        batch: BatchMode::Logged(BATCH_SIZE_WARN),
+        coerce: false
+    }
+}
+
+// the timestamp is bound, the text of a statement stays the same from write to write and its prepared id is reused,
+// the ttl has the few values of the Conf and of the writes, it stays in the text
+
+impl WriteOptions {
+    // using clause for inserts and updates
+    pub fn using(&self) -> String {
+        match (self.ttl, self.timestamp) {
+            (Some(ttl), Some(_)) => " using ttl ".to_string() + &ttl.to_string() + " and timestamp ?",
+            (Some(ttl), None) => " using ttl ".to_string() + &ttl.to_string(),
+            (None, Some(_)) => " using timestamp ?".to_string(),
+            (None, None) => "".to_string()
+        }
+    }
+    // using clause for deletes, they take no ttl
+    pub fn using_timestamp(&self) -> String {
+        match self.timestamp {
+            Some(_) => " using timestamp ?".to_string(),
+            None => "".to_string()
+        }
+    }
+    pub fn using_values(&self) -> Vec<Column> {
+        match self.timestamp {
+            Some(ts) => vec![Column::Bigint(ts)],
+            None => vec![]
+        }
+    }
+    // inserts have the using clause after their values
+    pub fn values_then_using(&self, mut values: Vec<Column>) -> Vec<Column> {
+        values.extend(self.using_values());
+
+        values
+    }
+    // updates and deletes have it before them
+    pub fn using_then_values(&self, values: Vec<Column>) -> Vec<Column> {
+        let mut res = self.using_values();
+
+        res.extend(values);
+
+        res
+    }
+}
+
 pub enum QueryType {
     Storaged,
     Value,
     Substring
 }
 
+pub enum CqlType<'a> {
+    Bigint,
+    Timestamp,
+    Text,
+    Double,
+    Udt(&'a str)
+}
+
+impl<'a> CqlType<'a> {
+    pub fn get_type(&self) -> String {
+        match self {
+            &CqlType::Bigint => "bigint".to_string(),
+            &CqlType::Timestamp => "timestamp".to_string(),
+            &CqlType::Text => "text".to_string(),
+            &CqlType::Double => "double".to_string(),
+            &CqlType::Udt(name) => "frozen<".to_string() + name + ">"
+        }
+    }
+    pub fn get_udt(&self) -> Option<&'a str> {
+        match self {
+            &CqlType::Udt(name) => Some(name),
+            _ => None
+        }
+    }
+    pub fn get_order(&self) -> &'static str {
+        match self {
+            &CqlType::Bigint => "desc",
+            &CqlType::Timestamp => "desc",
+            _ => "asc"
+        }
+    }
+}
+
 pub enum Field<'a> {
     Bigint(&'a str),
     Timestamp(&'a str),
     Text(&'a str),
-    Double(&'a str)
+    Double(&'a str),
+    List(&'a str, CqlType<'a>),
+    Set(&'a str, CqlType<'a>),
+    Map(&'a str, CqlType<'a>, CqlType<'a>),
+    Udt(&'a str, &'a str)
+}
+
+pub enum Order {
+    Asc,
+    Desc
+}
+
+impl Order {
+    pub fn get_order(&self) -> &'static str {
+        match self {
+            &Order::Asc => "asc",
+            &Order::Desc => "desc"
+        }
+    }
 }
 
 pub struct FieldConf<'a> {
     pub f: Field<'a>,
-    pub qt: QueryType
+    pub qt: QueryType,
+    pub order: Option<Order>,
+    // only nullable fields take FieldValue::Null in update
+    pub nullable: bool,
+    pub fill: Option<Fill<'a>>
 }
 
 pub fn new_fc(f: Field, qt: QueryType) -> FieldConf {
     FieldConf {
         f: f,
-        qt: qt
+        qt: qt,
+        order: None,
+        nullable: false,
+        fill: None
+    }
+}
+
+pub fn new_ordered_fc(f: Field, qt: QueryType, order: Order) -> FieldConf {
+    FieldConf {
+        f: f,
+        qt: qt,
+        order: Some(order),
+        nullable: false,
+        fill: None
+    }
+}
+
+pub fn new_nullable_fc(f: Field, qt: QueryType) -> FieldConf {
+    FieldConf {
+        f: f,
+        qt: qt,
+        order: None,
+        nullable: true,
+        fill: None
+    }
+}
+
+pub fn new_filled_fc<'a>(f: Field<'a>, qt: QueryType, fill: Fill<'a>) -> FieldConf<'a> {
+    FieldConf {
+        f: f,
+        qt: qt,
+        order: None,
+        nullable: false,
+        fill: Some(fill)
+    }
+}
+
+// Default and Generated go into rows that don't have the field yet,
+// Computed is worked out from the row on every write so it follows the fields it is made of
+pub enum Fill<'a> {
+    Default(Column),
+    Generated(Box<Fn(&HashMap<String, Column>) -> Column + Send + Sync + 'a>),
+    Computed(Box<Fn(&HashMap<String, Column>) -> Column + Send + Sync + 'a>)
+}
+
+// creation time of the row
+pub fn fill_now<'a>() -> Fill<'a> {
+    Fill::Generated(Box::new(|_| now()))
+}
+
+// lowercase copy of a text field, to look the field up case insensitively
+pub fn fill_lowercase<'a>(source: &'a str) -> Fill<'a> {
+    Fill::Computed(Box::new(move |row| {
+        match row.get(source) {
+            Some(&Column::String(ref s)) => Column::String(s.to_lowercase()),
+            _ => Column::String("".to_string())
+        }
+    }))
+}
+
+// what update does with a field, Unchanged leaves the stored value as it is
+#[derive(Clone)]
+pub enum FieldValue {
+    Set(Column),
+    Null,
+    Unchanged
+}
+
+impl<'a> FieldConf<'a> {
+    // clustering order of the field in its _by_field_ table, the field type decides unless overridden
+    pub fn get_order(&self) -> &'a str {
+        match self.order {
+            Some(ref o) => o.get_order(),
+            None => self.f.get_order()
+        }
+    }
+    pub fn is_desc(&self) -> bool {
+        self.get_order() == "desc"
     }
 }
 
@@ -52,7 +293,11 @@ impl<'a> Field<'a> {
             &Field::Bigint(name) => name,
             &Field::Timestamp(name) => name,
             &Field::Text(name) => name,
-            &Field::Double(name) => name
+            &Field::Double(name) => name,
+            &Field::List(name, _) => name,
+            &Field::Set(name, _) => name,
+            &Field::Map(name, _, _) => name,
+            &Field::Udt(name, _) => name
         }
     }
     pub fn get_order(&self) -> &'a str  {
@@ -60,16 +305,78 @@ impl<'a> Field<'a> {
             &Field::Bigint(_) => "desc",
             &Field::Timestamp(_) => "desc",
             &Field::Text(_) => "asc",
+            &Field::Set(_, ref t) => t.get_order(),
             _ => "asc"
         }
     }
+    pub fn get_type(&self) -> String {
+        match self {
+            &Field::Bigint(_) => "bigint".to_string(),
+            &Field::Timestamp(_) => "timestamp".to_string(),
+            &Field::Text(_) => "text".to_string(),
+            &Field::Double(_) => "double".to_string(),
+            &Field::List(_, ref t) => "list<".to_string() + &t.get_type() + ">",
+            &Field::Set(_, ref t) => "set<".to_string() + &t.get_type() + ">",
+            &Field::Map(_, ref k, ref v) => "map<".to_string() + &k.get_type() + "," + &v.get_type() + ">",
+            &Field::Udt(_, udt) => "frozen<".to_string() + udt + ">"
+        }
+    }
+    // type of the field column in its _by_field_ table, sets are fanned out one row per element
+    pub fn get_index_type(&self) -> String {
+        match self {
+            &Field::Set(_, ref t) => t.get_type(),
+            _ => self.get_type()
+        }
+    }
+    pub fn is_collection(&self) -> bool {
+        match self {
+            &Field::List(_, _) | &Field::Set(_, _) | &Field::Map(_, _, _) => true,
+            _ => false
+        }
+    }
+    // user-defined types this field depends on
+    pub fn get_udts(&self) -> Vec<&'a str> {
+        match self {
+            &Field::Udt(_, udt) => vec![udt],
+            &Field::List(_, ref t) | &Field::Set(_, ref t) => t.get_udt().into_iter().collect(),
+            &Field::Map(_, ref k, ref v) => k.get_udt().into_iter().chain(v.get_udt().into_iter()).collect(),
+            _ => vec![]
+        }
+    }
+    // nested values are bound as json text
+    pub fn is_nested(&self) -> bool {
+        self.get_udts().len() > 0
+    }
 }
 
 pub fn add_field(query: String, f: &Field) -> String {
-    match f {
-        &Field::Bigint(name) => query + name + " bigint,",
-        &Field::Timestamp(name) => query + name + " timestamp,",
-        &Field::Text(name) => query + name + " text,",
-        &Field::Double(name) => query + name + " double,"
+    query + f.get_name() + " " + &f.get_type() + ","
+}
+
+pub fn add_placeholder(query: String, f: &Field) -> String {
+    query + placeholder(f) + ","
+}
+
+// also for the field column of a _by_field_ table, where a set of udts has one udt per row
+pub fn placeholder(f: &Field) -> &'static str {
+    if f.is_nested() {
+        "fromJson(?)"
+    } else {
+        "?"
     }
-}
\ No newline at end of file
+}
+
+// elements of a set value, one _by_field_ row is written per element
+pub fn elements(c: &Column) -> Vec<Column> {
+    match c {
+        &Column::Set(ref v) => v.clone(),
+        _ => vec![]
+    }
+}
+
+// elements of old that are not in new
+pub fn removed_elements(old: &Column, new: &Column) -> Vec<Column> {
+    let new = elements(new);
+
+    elements(old).into_iter().filter(|x| !new.contains(x)).collect()
+}
@@ -11,11 +11,33 @@ use rustcql::shared::ResultBody;
 use rustcql::shared::BatchQuery;
 use rustcql::connect;
 
+use std::collections::HashMap;
+use std::time::Duration;
+
 use super::*;
 
 use proto::shared::*;
 use proto::conf::*;
 use proto::schema::*;
+use proto::session::*;
+use proto::batch::*;
+use proto::pool::*;
+use proto::retry::*;
+use proto::def::*;
+use proto::udt::*;
+use proto::options::*;
+use proto::row::*;
+use proto::error::Error;
+use proto::error::server_error;
+use proto::error::server_error_of;
+use proto::id::*;
+use proto::bucket::*;
+use proto::counter::*;
+use proto::bulk::*;
+#[cfg(feature = "async")]
+use proto::nonblocking::*;
+use proto::conf::conf_list::keep_live;
+use proto::conf::conf_delete::is_deleted;
 
 pub struct Entity<'a> {
     pub test1: FieldConf<'a>,
@@ -68,17 +90,17 @@ fn test_get_conf() {
 #[test]
 fn test_get_schema() {
     let c = get_conf();
-    let s = c.get_schema();
+    let s = c.get_schema().unwrap();
 }
 
 //#[ignore]
 #[test]
 pub fn test_create_schema() {
 
-    let mut conn = connect(HOST.to_string()).unwrap();
+    let mut conn = open(HOST.to_string()).unwrap();
 
     create_schema(&mut conn, vec![
-        get_conf().get_schema()
+        get_conf().get_schema().unwrap()
     ], 1);
 }
 
@@ -88,7 +110,7 @@ fn test_insert_all() {
 
     let c = get_conf();
 
-    let mut conn = connect(HOST.to_string()).unwrap();
+    let mut conn = open(HOST.to_string()).unwrap();
 
     c.insert_all(&mut conn, 1, 1, vec![
         Column::String("asd".to_string()),
@@ -97,7 +119,1511 @@ fn test_insert_all() {
         Column::Double(1.333333),
         now(),
         now()
-    ], Quorum);
+    ], Quorum, &new_write_options());
 
     let r = c.first_by_id(&mut conn, 1, 1).unwrap();
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_set_field_schema() {
+    let c = new_conf("tagged", (), Some(vec![
+        new_fc(Field::Text("title"), QueryType::Storaged),
+        new_fc(Field::Set("tags", CqlType::Text), QueryType::Value)
+        ]), None, None);
+
+    let s = c.get_schema().unwrap();
+
+    assert!(s.queries.iter().any(|q| q.starts_with("create table tagged ") && q.contains("tags set<text>,")));
+    assert!(s.queries.iter().any(|q| q.starts_with("create table tagged_by_field_tags ") && q.contains("tags text,")));
+}
+
+#[test]
+fn test_udt_schema() {
+    let mut c = new_conf("person", (), Some(vec![
+        new_fc(Field::Text("name"), QueryType::Value),
+        new_fc(Field::Udt("home", "address"), QueryType::Storaged),
+        new_fc(Field::List("places", CqlType::Udt("place")), QueryType::Storaged)
+        ]), None, None);
+
+    c.types = Some(vec![
+        new_udt("place", vec![Field::Text("title"), Field::Udt("address", "address")]),
+        new_udt("address", vec![Field::Text("street"), Field::Text("city"), Field::Text("zip")])
+        ]);
+
+    let s = c.get_schema().unwrap();
+
+    assert_eq!(s.queries[0], "create type if not exists address (street text,city text,zip text)");
+    assert_eq!(s.queries[1], "create type if not exists place (title text,address frozen<address>)");
+    assert!(s.queries.iter().any(|q| q.contains("places list<frozen<place>>,")));
+}
+
+#[test]
+fn test_nested_json() {
+    let v = Nested::Udt(vec![
+        ("street", Nested::Value(Column::String("Main \"1\"".to_string()))),
+        ("zip", Nested::Value(Column::Bigint(10001)))
+        ]);
+
+    assert_eq!(v.to_json().unwrap(), "{\"street\":\"Main \\\"1\\\"\",\"zip\":10001}");
+}
+
+#[test]
+fn test_udt_cycle() {
+    let mut c = new_conf("person", (), Some(vec![
+        new_fc(Field::Udt("home", "address"), QueryType::Storaged)
+        ]), None, None);
+
+    c.types = Some(vec![
+        new_udt("address", vec![Field::Udt("owner", "owner")]),
+        new_udt("owner", vec![Field::Udt("home", "address")])
+        ]);
+
+    match c.get_schema() {
+        Err(Error::Cycle(_)) => {}
+        _ => panic!("address and owner depend on each other")
+    }
+}
+
+#[test]
+fn test_udt_set_index() {
+    let c = new_conf("person", (), Some(vec![
+        new_fc(Field::Text("name"), QueryType::Storaged),
+        new_fc(Field::Set("places", CqlType::Udt("place")), QueryType::Value)
+        ]), None, None);
+
+    let fields = c.fields.as_ref().unwrap();
+
+    let a = "{\"title\":\"a\"}".to_string();
+    let b = "{\"title\":\"b\"}".to_string();
+
+    let places = Column::Set(vec![Column::String(a.clone()), Column::String(b.clone())]);
+
+    let batch = c.get_batch_for_insert(1, 1, vec![&fields[0], &fields[1]], vec![Column::String("Ann".to_string()), places.clone()], None, &new_write_options());
+
+    // one index row per udt, the set itself goes to the main table as a single json array
+    assert_eq!(batch.len(), 3);
+
+    for (bq, key) in batch.iter().zip(vec![&a, &b]) {
+        match bq {
+            &BatchQuery::SimpleWithParams(ref query, ref values) => {
+                assert!(query.starts_with("insert into test1.person_by_field_places (group,id,name,places) values (?,?,?,fromJson(?))"));
+                assert_eq!(values[3], Column::String(key.clone()));
+            }
+            _ => panic!("unexpected batch query")
+        }
+    }
+
+    match batch[2] {
+        BatchQuery::SimpleWithParams(ref query, ref values) => {
+            assert!(query.starts_with("insert into test1.person (group,id,name,places) values (?,?,?,fromJson(?))"));
+            assert_eq!(values[3], Column::String("[".to_string() + &a + "," + &b + "]"));
+        }
+        _ => panic!("unexpected batch query")
+    }
+
+    let batch = c.get_batch_for_remove(1, 1, &fields[1], places, None, &new_write_options());
+
+    match batch.last() {
+        Some(&BatchQuery::SimpleWithParams(ref query, ref values)) => {
+            assert!(query.ends_with(" set places = places - fromJson(?) where group = ? and id = ?"));
+            assert_eq!(values[1], Column::String("[".to_string() + &a + "," + &b + "]"));
+        }
+        _ => panic!("unexpected batch query")
+    }
+
+    match batch[0] {
+        BatchQuery::SimpleWithParams(ref query, _) => assert!(query.ends_with(" where group = ? and places = fromJson(?) and id = ?")),
+        _ => panic!("unexpected batch query")
+    }
+}
+
+#[test]
+fn test_field_order_override() {
+    let c = new_conf("event", (), Some(vec![
+        new_fc(Field::Timestamp("happened_at"), QueryType::Value),
+        new_ordered_fc(Field::Timestamp("seen_at"), QueryType::Value, Order::Asc),
+        new_ordered_fc(Field::Double("score"), QueryType::Value, Order::Desc)
+        ]), None, None);
+
+    let s = c.get_schema().unwrap();
+
+    assert!(s.queries.iter().any(|q| q.contains("clustering order by (happened_at desc,id desc)")));
+    assert!(s.queries.iter().any(|q| q.contains("clustering order by (seen_at asc,id desc)")));
+    assert!(s.queries.iter().any(|q| q.contains("clustering order by (score desc,id desc)")));
+}
+
+#[test]
+fn test_table_options() {
+    let mut c = new_conf("session", (), Some(vec![
+        new_fc(Field::Text("token"), QueryType::Value)
+        ]), None, None);
+
+    c.options.default_time_to_live = Some(3600);
+    c.options.compaction = Some(Compaction::TimeWindow { unit: WindowUnit::Hours, size: 1 });
+
+    let mut o = new_table_options();
+    o.compaction = Some(Compaction::Leveled { sstable_size_in_mb: Some(160) });
+    o.gc_grace_seconds = Some(3600);
+
+    c.overrides = Some(vec![("session_by_field_token", o)]);
+
+    let s = c.get_schema().unwrap();
+
+    assert!(s.queries[1].ends_with("and gc_grace_seconds = 86400 and compaction = {'class': 'TimeWindowCompactionStrategy', 'compaction_window_unit': 'HOURS', 'compaction_window_size': '1'} and default_time_to_live = 3600"));
+    assert!(s.queries[0].ends_with("and gc_grace_seconds = 3600 and compaction = {'class': 'LeveledCompactionStrategy', 'sstable_size_in_mb': '160'} and default_time_to_live = 3600"));
+}
+
+#[test]
+fn test_write_ttl() {
+    let mut c = get_conf();
+
+    c.ttl = Some(86400);
+
+    let values = vec![
+        Column::String("asd".to_string()),
+        now(),
+        Column::String("qwe".to_string()),
+        Column::Double(1.333333),
+        now(),
+        now()
+    ];
+
+    let batch = c.get_batch_for_insert_all(1, 1, values.clone(), None, &new_write_options());
+
+    for bq in batch.iter() {
+        match bq {
+            &BatchQuery::SimpleWithParams(ref query, _) => assert!(query.contains(" using ttl 86400 and timestamp ")),
+            _ => panic!("unexpected batch query")
+        }
+    }
+
+    let mut opts = new_write_options();
+    opts.ttl = Some(60);
+    opts.timestamp = Some(1000);
+
+    let batch = c.get_batch_for_insert_all(1, 1, values, None, &opts);
+
+    for bq in batch.iter() {
+        match bq {
+            &BatchQuery::SimpleWithParams(ref query, ref values) => {
+                assert!(query.ends_with(" using ttl 60 and timestamp ?"));
+                assert_eq!(values.last(), Some(&Column::Bigint(1000)));
+            }
+            _ => panic!("unexpected batch query")
+        }
+    }
+}
+
+#[test]
+fn test_prepared_cache() {
+    let c = get_conf();
+
+    let mut opts = new_write_options();
+
+    let text = |opts: &WriteOptions| c.get_batch_for_insert(1, 1, vec![&c.e.test4], vec![Column::Double(1.5)], None, opts).into_iter().map(|bq| match bq {
+        BatchQuery::SimpleWithParams(query, _) => query,
+        _ => panic!("unexpected batch query")
+    }).collect::<Vec<String>>();
+
+    opts.timestamp = Some(1000);
+    let first = text(&opts);
+
+    opts.timestamp = Some(2000);
+    assert_eq!(text(&opts), first);
+
+    let mut cache = new_prepared_cache(2);
+
+    cache.insert("a".to_string(), vec![1]);
+    cache.insert("b".to_string(), vec![2]);
+
+    assert_eq!(cache.get("a"), Some(vec![1]));
+
+    // b is the least recently used
+    cache.insert("c".to_string(), vec![3]);
+
+    assert_eq!(cache.len(), 2);
+    assert!(cache.contains("a"));
+    assert!(!cache.contains("b"));
+}
+
+#[test]
+fn test_update_null() {
+    let c = get_conf();
+    let e = get_entity();
+
+    let mut state = HashMap::new();
+    state.insert("test2".to_string(), now());
+    state.insert("test4".to_string(), Column::Double(1.5));
+
+    let mut opts = new_write_options();
+    opts.timestamp = Some(1000);
+
+    let batch = c.get_batch_for_update(1, 1,
+        vec![&e.test2], vec![Column::Timestamp(2000)],
+        vec![&e.test4],
+        Some(state), &opts);
+
+    let queries = batch.iter().map(|bq| match bq {
+        &BatchQuery::SimpleWithParams(ref query, _) => query.clone(),
+        _ => panic!("unexpected batch query")
+    }).collect::<Vec<String>>();
+
+    assert_eq!(queries[0], "delete from test1.test_by_field_test4 using timestamp ? where group = ? and test4 = ? and id = ?");
+    assert!(queries.contains(&"update test1.test using timestamp ? set test4 = null where group = ? and id = ?".to_string()));
+    assert!(queries.contains(&"update test1.test_by_field_test2 using timestamp ? set test4 = null where group = ? and test2 = ? and id = ?".to_string()));
+
+    // only a null, the index rows of the other fields are keyed by state
+    let mut state = HashMap::new();
+    state.insert("test1".to_string(), Column::String("asd".to_string()));
+    state.insert("test2".to_string(), Column::Timestamp(2000));
+    state.insert("test4".to_string(), Column::Double(1.5));
+
+    let batch = c.get_batch_for_update(1, 1, vec![], vec![], vec![&e.test4], Some(state), &opts);
+
+    let updates = batch.iter().filter_map(|bq| match bq {
+        &BatchQuery::SimpleWithParams(ref query, ref values) if query.starts_with("update ") => Some((query.clone(), values.clone())),
+        _ => None
+    }).collect::<Vec<(String, Vec<Column>)>>();
+
+    assert_eq!(updates.len(), 3);
+    assert!(updates.iter().any(|&(ref q, ref v)| q == "update test1.test_by_field_test1 using timestamp ? set test4 = null where group = ? and test1 = ? and id = ?" && v[2] == Column::String("asd".to_string())));
+    assert!(updates.iter().any(|&(ref q, ref v)| q == "update test1.test_by_field_test2 using timestamp ? set test4 = null where group = ? and test2 = ? and id = ?" && v[2] == Column::Timestamp(2000)));
+
+    // no state, nothing to delete
+    let batch = c.get_batch_for_update(1, 1, vec![], vec![], vec![&e.test4], None, &opts);
+
+    assert_eq!(batch.len(), 1);
+}
+
+#[test]
+fn test_fill() {
+    let c = new_conf("person", (), Some(vec![
+        new_fc(Field::Text("name"), QueryType::Storaged),
+        new_filled_fc(Field::Text("name_lower"), QueryType::Value, fill_lowercase("name")),
+        new_filled_fc(Field::Timestamp("created"), QueryType::Storaged, fill_now()),
+        new_filled_fc(Field::Text("status"), QueryType::Value, Fill::Default(Column::String("new".to_string())))
+    ]), None, None);
+
+    let mut f = vec![&c.fields.as_ref().unwrap()[0]];
+    let mut values = vec![Column::String("Ann".to_string())];
+
+    c.fill(&mut f, &mut values, &[], &None);
+
+    let names = f.iter().map(|x| x.f.get_name()).collect::<Vec<&str>>();
+
+    assert_eq!(names, vec!["name", "created", "status", "name_lower"]);
+    assert!(values[2] == Column::String("new".to_string()));
+    assert!(values[3] == Column::String("ann".to_string()));
+
+    // the row exists, only the computed field follows the new name
+    let mut state = HashMap::new();
+    state.insert("created".to_string(), Column::Timestamp(1000));
+    state.insert("status".to_string(), Column::String("done".to_string()));
+
+    let mut f = vec![&c.fields.as_ref().unwrap()[0]];
+    let mut values = vec![Column::String("Bob".to_string())];
+
+    c.fill(&mut f, &mut values, &[], &Some(state.clone()));
+
+    assert_eq!(f.len(), 2);
+    assert!(values[1] == Column::String("bob".to_string()));
+
+    // a nulled name is gone from the row the computed field sees, a nulled status gets no default
+    state.insert("name".to_string(), Column::String("Bob".to_string()));
+    state.remove("status");
+
+    let fields = c.fields.as_ref().unwrap();
+
+    let mut f = vec![];
+    let mut values = vec![];
+
+    c.fill(&mut f, &mut values, &[&fields[0], &fields[3]], &Some(state));
+
+    let names = f.iter().map(|x| x.f.get_name()).collect::<Vec<&str>>();
+
+    assert_eq!(names, vec!["name_lower"]);
+    assert!(values[0] == Column::String("".to_string()));
+
+    let mut values = vec![
+        Column::String("Eve".to_string()),
+        Column::String("".to_string()),
+        Column::Timestamp(1000),
+        Column::String("new".to_string())
+    ];
+
+    c.fill_all(&mut values);
+
+    assert!(values[1] == Column::String("eve".to_string()));
+}
+
+#[test]
+fn test_ids() {
+    let mut flake = new_simpleflake();
+    let mut seq = new_sequence();
+
+    let before = UTC::now().timestamp() * 1000;
+
+    let a = flake.next_id(1);
+    let b = seq.next_id(1);
+    let c = seq.next_id(1);
+
+    assert!(id_millis(a) >= before);
+    assert!(id_millis(a) - before < 60000);
+    assert!(c > b);
+    assert_eq!(id_millis(id_at(before)), before);
+    assert_eq!(id_timestamp(id_at(before)).unwrap().timestamp(), before / 1000);
+    assert!(id_timestamp(i64::max_value()).is_some() && id_timestamp(i64::min_value()).is_some());
+    assert!(millis_timestamp(i64::max_value()).is_none());
+}
+
+#[test]
+fn test_sequences_apart() {
+    use std::collections::HashSet;
+
+    let mut a = new_sequence_on(1);
+    let mut b = new_sequence_on(2);
+    let mut ids = HashSet::new();
+
+    for _ in 0..1000 {
+        ids.insert(a.next_id(1));
+        ids.insert(b.next_id(1));
+    }
+
+    assert_eq!(ids.len(), 2000);
+}
+
+#[test]
+fn test_list_by_time_queries() {
+    let c = get_conf();
+
+    let from = UTC.ymd(2016, 1, 4).and_hms(0, 0, 0);
+    let to = UTC.ymd(2016, 1, 11).and_hms(0, 0, 0);
+
+    let queries = c.list_by_time_queries(1, &from, &to, None).unwrap();
+
+    assert_eq!(queries[0].0, "select * from test1.test where group = ? and id >= ? and id < ? limit 10");
+    assert!(queries[0].1[1] == Column::Bigint(id_at_time(&from)));
+    assert!(queries[0].1[2] == Column::Bigint(id_at_time(&to)));
+
+    let last = id_at_time(&to) - 1000;
+
+    let queries = c.list_by_time_queries(1, &from, &to, Some(last)).unwrap();
+
+    assert!(queries[0].1[2] == Column::Bigint(last));
+    assert_eq!(id_timestamp(id_at_time(&from)), Some(from));
+}
+
+#[test]
+fn test_bucketing() {
+    let mut c = get_conf();
+
+    c.bucketing = Some(Bucketing::Day(7));
+
+    let s = c.get_schema().unwrap();
+
+    assert!(s.queries.iter().any(|q| q.contains("primary key ((group,bucket), id)")));
+    assert!(s.queries.iter().any(|q| q.contains("primary key ((group,bucket),test4,id)")));
+
+    let id = id_at_time(&UTC.ymd(2016, 1, 4).and_hms(12, 0, 0));
+    let day = Column::Bigint(16804);
+
+    let (query, values) = c.first_by_id_query(1, id);
+
+    assert_eq!(query, "select * from test1.test where group = ? and bucket = ? and id = ? limit 1");
+    assert!(values[1] == day);
+
+    let batch = c.get_batch_for_insert_all(1, id, vec![
+        Column::String("asd".to_string()),
+        now(),
+        Column::String("qwe".to_string()),
+        Column::Double(1.333333),
+        now(),
+        now()
+    ], None, &new_write_options());
+
+    for bq in batch.iter() {
+        match bq {
+            &BatchQuery::SimpleWithParams(ref query, ref values) => {
+                assert!(query.contains(" (group,bucket,id,"));
+                assert!(values[1] == day);
+            }
+            _ => panic!("unexpected batch query")
+        }
+    }
+
+    assert_eq!(c.list_with_limit_by_id_queries(1, None).len(), 7);
+
+    // a cursor older than the window still pages back from itself
+    let queries = c.list_with_limit_by_id_queries(1, Some(id));
+
+    assert_eq!(queries.len(), 7);
+    assert!(queries[0].1[1] == day);
+    assert!(queries[6].1[1] == Column::Bigint(16798));
+
+    let from = UTC.ymd(2016, 1, 4).and_hms(0, 0, 0);
+
+    assert_eq!(c.list_by_time_queries(1, &from, &UTC.ymd(2016, 1, 11).and_hms(0, 0, 0), None).unwrap().len(), 7);
+    assert!(c.list_by_time_queries(1, &from, &UTC.ymd(2016, 1, 12).and_hms(0, 0, 0), None).is_err());
+
+    assert!(c.check_id(id).is_ok());
+
+    match c.check_id(1) {
+        Err(Error::NotSimpleflake(1)) => {}
+        _ => panic!("1 is not a simpleflake id")
+    }
+
+    c.bucketing = Some(Bucketing::Hash(4));
+
+    let queries = c.list_with_limit_by_id_queries(1, Some(6));
+    let shard = Bucketing::Hash(4).bucket_of(6);
+
+    // the shard of id 6 goes on after it, the later shards from the top
+    assert_eq!(queries.len() as i64, 4 - shard);
+    assert!(queries[0].1 == vec![Column::Bigint(1), Column::Bigint(shard), Column::Bigint(6)]);
+
+    for (i, q) in queries.iter().enumerate().skip(1) {
+        assert!(q.1 == vec![Column::Bigint(1), Column::Bigint(shard + i as i64)]);
+    }
+}
+
+#[test]
+fn test_hash_spread() {
+    let hash = Bucketing::Hash(8);
+    let start = id_at(UTC::now().timestamp() * 1000);
+
+    // sequential ids, ids of one millisecond and the first ids of consecutive milliseconds
+    let runs: Vec<Vec<i64>> = vec![
+        (1..1000).collect(),
+        (0..1000).map(|i| start + (i << SEQUENCE_NODE_SHIFT)).collect(),
+        (0..1000).map(|i| id_at(1500000000000 + i)).collect()
+    ];
+
+    for ids in runs.iter() {
+        let mut counts = vec![0; 8];
+
+        for id in ids.iter() {
+            counts[hash.bucket_of(*id) as usize] += 1;
+        }
+
+        assert!(counts.iter().all(|n| *n > 60), "{:?}", counts);
+    }
+}
+
+#[test]
+fn test_counters() {
+    let mut c = get_conf();
+
+    c.counters = true;
+
+    let s = c.get_schema().unwrap();
+
+    assert!(s.queries.iter().any(|q| q.starts_with("create table test_counts (group bigint, count counter, primary key (group))")));
+    assert!(s.queries.iter().any(|q| q.starts_with("create table test_counts_by_field_test4 (group bigint, test4 double, count counter, primary key (group,test4))")));
+
+    c.options.default_time_to_live = Some(3600);
+
+    let s = c.get_schema().unwrap();
+
+    assert!(s.queries.iter().any(|q| q.starts_with("create table test (") && q.contains("default_time_to_live = 3600")));
+    assert!(s.queries.iter().filter(|q| q.contains(" counter,")).all(|q| !q.contains("default_time_to_live")));
+
+    let mut old = HashMap::new();
+    old.insert("test3".to_string(), Column::String("qwe".to_string()));
+    old.insert("test4".to_string(), Column::Double(1.5));
+
+    let mut new = old.clone();
+    new.insert("test4".to_string(), Column::Double(2.5));
+
+    let queries = |batch: Vec<BatchQuery>| batch.into_iter().map(|bq| match bq {
+        BatchQuery::SimpleWithParams(query, _) => query,
+        _ => panic!("unexpected batch query")
+    }).collect::<Vec<String>>();
+
+    let counter_queries = |counters: Vec<CounterUpdate>| counters.into_iter().map(|cu| cu.query).collect::<Vec<String>>();
+
+    assert_eq!(counter_queries(c.count_jobs(1, Some(&old), Some(&new))), vec![
+        "update test1.test_counts_by_field_test4 set count = count - 1 where group = ? and test4 = ?",
+        "update test1.test_counts_by_field_test4 set count = count + 1 where group = ? and test4 = ?"
+    ]);
+
+    assert_eq!(counter_queries(c.count_jobs(1, None, Some(&new)))[0], "update test1.test_counts set count = count + 1 where group = ?");
+    assert_eq!(counter_queries(c.count_jobs(1, Some(&old), None)).len(), 2);
+
+    let batch = queries(c.get_batch_for_delete(1, 1, Some(old), &new_write_options()));
+
+    assert!(batch[0].starts_with("delete from test1.test_by_field_test4 using timestamp "));
+    assert!(batch[1].starts_with("delete from test1.test using timestamp "));
+
+    assert_eq!(c.count_queries(1)[0].0, "select count(*) from test1.test where group = ?");
+}
+
+#[test]
+fn test_soft_delete() {
+    let mut c = get_conf();
+
+    c.soft_delete = true;
+
+    assert!(c.get_schema().unwrap().queries.iter().any(|q| q.starts_with("create table test (group bigint,\n        id bigint, deleted_at timestamp,")));
+
+    let mut row = HashMap::new();
+    row.insert("id".to_string(), Column::Bigint(1));
+    row.insert("test4".to_string(), Column::Double(1.5));
+
+    let queries = |batch: Vec<BatchQuery>| batch.into_iter().map(|bq| match bq {
+        BatchQuery::SimpleWithParams(query, _) => query,
+        _ => panic!("unexpected batch query")
+    }).collect::<Vec<String>>();
+
+    let batch = queries(c.get_batch_for_delete(1, 1, Some(row.clone()), &new_write_options()));
+
+    assert_eq!(batch.len(), 2);
+    assert!(batch[0].starts_with("delete from test1.test_by_field_test4 using timestamp "));
+    assert!(batch[1].starts_with("update test1.test using timestamp "));
+    assert!(batch[1].ends_with(" set deleted_at = ? where group = ? and id = ?"));
+
+    let batch = queries(c.get_batch_for_purge(1, 1, Some(row.clone()), &new_write_options()));
+
+    assert!(batch[1].starts_with("delete from test1.test using timestamp "));
+
+    let batch = queries(c.get_batch_for_restore(1, 1, row.clone(), &new_write_options()));
+
+    assert!(batch[0].starts_with("insert into test1.test_by_field_test4 "));
+    assert!(batch[1].ends_with(" set deleted_at = null where group = ? and id = ?"));
+
+    let batch = queries(c.get_batch_for_insert(1, 1, vec![&c.e.test4], vec![Column::Double(2.5)], None, &new_write_options()));
+
+    assert!(batch.last().unwrap().starts_with("insert into test1.test (group,id,deleted_at,test4) values (?,?,null,?)"));
+
+    let mut deleted = row.clone();
+    deleted.insert("deleted_at".to_string(), Column::Timestamp(1000));
+
+    assert!(is_deleted(&deleted));
+
+    // writing test4 over the deleted row writes the index rows of its other fields again
+    let mut deleted_state = deleted.clone();
+    deleted_state.insert("test2".to_string(), Column::Timestamp(2000));
+
+    let mut f = vec![&c.e.test4];
+    let mut values = vec![Column::Double(2.5)];
+
+    let state = c.revive(Some(deleted_state), &mut f, &mut values, &[]);
+
+    assert!(state.is_none());
+    assert_eq!(values, vec![Column::Double(2.5), Column::Timestamp(2000)]);
+
+    let batch = queries(c.get_batch_for_insert(1, 1, f, values, state, &new_write_options()));
+
+    assert!(batch.iter().any(|q| q.starts_with("insert into test1.test_by_field_test2 ")));
+    assert!(batch.iter().any(|q| q.starts_with("insert into test1.test_by_field_test4 ")));
+    assert!(!batch.iter().any(|q| q.starts_with("delete ")));
+
+    assert!(c.revive(Some(row.clone()), &mut vec![], &mut vec![], &[]).is_some());
+
+    // insert_all over the deleted row is an insert of a new row
+    let mut values = vec![Column::Double(2.5)];
+
+    assert!(c.revive_all(Some(deleted.clone()), &mut values).is_none());
+    assert_eq!(values, vec![Column::Double(2.5)]);
+    assert!(c.revive_all(Some(row.clone()), &mut values).is_some());
+
+    // a full page of deleted rows goes on from the last of them
+    let mut res = vec![];
+
+    assert_eq!(keep_live(&mut res, vec![deleted.clone(); 10]), Some(1));
+    assert_eq!(keep_live(&mut res, vec![row.clone(), deleted]), None);
+    assert_eq!(res.len(), 1);
+}
+
+#[test]
+fn test_counter_conf() {
+    let c = new_counter_conf("page", vec!["views", "likes"]);
+
+    assert!(c.get_schema().queries[0].starts_with("create table page (group bigint, id bigint, views counter, likes counter, primary key (group, id)) with clustering order by (id desc)"));
+
+    let mut ttl = new_counter_conf("page", vec!["views"]);
+
+    ttl.options.default_time_to_live = Some(60);
+
+    assert!(!ttl.get_schema().queries[0].contains("default_time_to_live"));
+
+    let update = c.get_update(1, 2, vec![("views", 1), ("likes", -3)]).unwrap();
+
+    assert_eq!(update.query, "update test1.page set views = views + ?, likes = likes - ? where group = ? and id = ?");
+    assert_eq!(update.values, vec![Column::Bigint(1), Column::Bigint(3), Column::Bigint(1), Column::Bigint(2)]);
+
+    assert!(c.get_update(1, 2, vec![("shares", 1)]).is_err());
+    assert!(c.get_update(1, 2, vec![]).is_err());
+    assert!(c.get_update(1, 2, vec![("views", ::std::i64::MIN)]).is_err());
+    assert!(negate(::std::i64::MAX).unwrap() == -::std::i64::MAX);
+
+    let (query, _) = counter_query(vec![update.clone(), update]);
+
+    assert!(query.starts_with("begin counter batch update test1.page"));
+}
+
+#[test]
+fn test_clock() {
+    let clock = new_clock();
+
+    let a = clock.next();
+    let b = clock.next();
+
+    assert!(b > a);
+
+    let c = get_conf();
+
+    // derived write timestamps don't tie either
+    let first = c.get_write_options(&new_write_options()).timestamp.unwrap();
+    let second = c.get_write_options(&new_write_options()).timestamp.unwrap();
+
+    assert!(second > first);
+}
+
+#[test]
+fn test_batch_plan() {
+    let c = get_conf();
+
+    let batch = || c.get_batch_for_insert_all(1, 1, vec![
+        Column::String("asd".to_string()),
+        now(),
+        Column::String("qwe".to_string()),
+        Column::Double(1.333333),
+        now(),
+        now()
+    ], None, &new_write_options());
+
+    let count = batch().len();
+
+    let planned = plan(batch(), &BatchMode::Logged(BATCH_SIZE_WARN));
+    assert_eq!(planned.len(), 1);
+
+    let planned = plan(batch(), &BatchMode::Logged(1));
+    assert_eq!(planned.len(), count);
+
+    // every table is its own partition
+    let planned = plan(batch(), &BatchMode::Unlogged);
+    assert_eq!(planned.len(), count);
+}
+
+#[test]
+fn test_collection_index_rows() {
+    let c = new_conf("note", (), Some(vec![
+        new_fc(Field::Text("title"), QueryType::Value),
+        new_fc(Field::Set("tags", CqlType::Text), QueryType::Value),
+        new_fc(Field::List("lines", CqlType::Text), QueryType::Storaged)
+        ]), None, None);
+
+    let fields = c.fields.as_ref().unwrap();
+
+    let text = |s: &str| Column::String(s.to_string());
+
+    let mut row = HashMap::new();
+    row.insert("id".to_string(), Column::Bigint(1));
+    row.insert("title".to_string(), text("a"));
+    row.insert("tags".to_string(), Column::Set(vec![text("x"), text("y")]));
+
+    let mut deleted = row.clone();
+    deleted.insert("deleted_at".to_string(), Column::Timestamp(1000));
+
+    let queries = |batch: Vec<BatchQuery>| batch.into_iter().map(|bq| match bq {
+        BatchQuery::SimpleWithParams(query, values) => (query, values),
+        _ => panic!("unexpected batch query")
+    }).collect::<Vec<(String, Vec<Column>)>>();
+
+    // the copy of the list in every index row follows the append
+    let batch = queries(c.get_batch_for_append(1, 1, &fields[2], Column::List(vec![text("l")]), Some(row.clone()), &new_write_options()));
+
+    assert_eq!(batch.len(), 4);
+    assert!(batch[0].0.starts_with("update test1.note_by_field_title using timestamp "));
+    assert!(batch[0].0.ends_with(" set lines = lines + ? where group = ? and title = ? and id = ?"));
+    assert_eq!(batch[0].1[1..], [Column::List(vec![text("l")]), Column::Bigint(1), text("a"), Column::Bigint(1)]);
+    assert!(batch[1].0.ends_with(" set lines = lines + ? where group = ? and tags = ? and id = ?"));
+    assert_eq!(batch[1].1[3], text("x"));
+    assert_eq!(batch[2].1[3], text("y"));
+    assert!(batch[3].0.starts_with("update test1.note "));
+
+    // new elements get their index rows, the rows of the other fields get the set
+    let batch = queries(c.get_batch_for_append(1, 1, &fields[1], Column::Set(vec![text("z")]), Some(row.clone()), &new_write_options()));
+
+    assert_eq!(batch.len(), 3);
+    assert!(batch[0].0.starts_with("insert into test1.note_by_field_tags (group,id,title,tags) values (?,?,?,?)"));
+    assert_eq!(batch[0].1[2..4], [text("a"), text("z")]);
+    assert!(batch[1].0.ends_with(" set tags = tags + ? where group = ? and title = ? and id = ?"));
+
+    // a row that isn't there yet gets index rows too
+    let batch = queries(c.get_batch_for_append(1, 1, &fields[1], Column::Set(vec![text("z")]), None, &new_write_options()));
+
+    assert_eq!(batch.len(), 2);
+    assert!(batch[0].0.starts_with("insert into test1.note_by_field_tags (group,id,tags) values (?,?,?)"));
+
+    // a deleted row doesn't
+    let batch = queries(c.get_batch_for_append(1, 1, &fields[1], Column::Set(vec![text("z")]), Some(deleted.clone()), &new_write_options()));
+
+    assert_eq!(batch.len(), 1);
+
+    let batch = queries(c.get_batch_for_remove(1, 1, &fields[1], Column::Set(vec![text("x")]), Some(row.clone()), &new_write_options()));
+
+    assert_eq!(batch.len(), 3);
+    assert!(batch[0].0.starts_with("delete from test1.note_by_field_tags "));
+    assert!(batch[1].0.ends_with(" set tags = tags - ? where group = ? and title = ? and id = ?"));
+
+    let batch = queries(c.get_batch_for_remove(1, 1, &fields[1], Column::Set(vec![text("x")]), Some(deleted), &new_write_options()));
+
+    assert_eq!(batch.len(), 1);
+}
+
+#[test]
+fn test_partition_of() {
+    let mut c = get_conf();
+
+    c.bucketing = Some(Bucketing::Hash(4));
+
+    let mut opts = new_write_options();
+    opts.timestamp = Some(1000);
+
+    // items are bound before the key of the row
+    let batch = c.get_batch_for_remove(1, 7, &c.e.test1, Column::Set(vec![]), None, &opts);
+
+    let (table, key) = partition_of(&batch[0]);
+
+    assert_eq!(table, "test1.test");
+    assert_eq!(key, c.partition_values(1, 7));
+
+    let batch = c.get_batch_for_insert(1, 7, vec![&c.e.test4], vec![Column::Double(1.5)], None, &opts);
+
+    for bq in batch.iter() {
+        assert_eq!(partition_of(bq).1, c.partition_values(1, 7));
+    }
+
+    assert_eq!(partition_bounds("update test1.test set test1 = test1 + ? where group = ? and id = ?"), (1, 1));
+}
+
+#[test]
+fn test_pool_failover() {
+    use std::net::TcpListener;
+    use std::thread;
+
+    // accepts and hangs up before the handshake
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let hangs_up = listener.local_addr().unwrap().to_string();
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            drop(stream);
+        }
+    });
+
+    // nothing listens there
+    let refuses = {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().to_string()
+    };
+
+    let mut pool = new_pool(vec![&hangs_up, &refuses], 2);
+
+    assert!(!pool.is_up(0));
+    assert!(!pool.is_up(1));
+
+    let result = pool.run(|s| s.query("select now() from system.local".to_string(), Quorum));
+
+    assert!(result.is_err());
+}
+
+// stands in for a session, it knows the host it was opened to
+struct FakeSession {
+    addr: String
+}
+
+fn fake_connect(addr: String) -> std::io::Result<FakeSession> {
+    Ok(FakeSession { addr: addr })
+}
+
+#[test]
+fn test_pool_server_error() {
+    let mut pool = new_pool_with(vec!["a", "b"], 1, fake_connect);
+
+    let result: std::io::Result<()> = pool.run(|_| Err(server_error(READ_TIMEOUT, "timeout".to_string())));
+
+    match result {
+        Err(ref e) => assert_eq!(server_error_of(e).map(|x| x.code), Some(READ_TIMEOUT)),
+        Ok(_) => panic!("the server error comes back")
+    }
+
+    assert!(pool.is_up(0));
+    assert!(pool.is_up(1));
+}
+
+#[test]
+fn test_pool_failover_to_live_host() {
+    use std::io::ErrorKind;
+
+    let mut pool = new_pool_with(vec!["a", "b"], 1, fake_connect);
+
+    let result = pool.run_idempotent(|s| {
+        if s.addr == "a" {
+            Err(std::io::Error::new(ErrorKind::ConnectionReset, "reset"))
+        } else {
+            Ok(s.addr.clone())
+        }
+    });
+
+    assert_eq!(result.unwrap(), "b");
+    assert!(!pool.is_up(0));
+    assert!(pool.is_up(1));
+
+    // the next run skips the down host
+    assert_eq!(pool.run(|s| Ok(s.addr.clone())).unwrap(), "b");
+}
+
+#[test]
+fn test_pool_no_replay() {
+    use std::io::ErrorKind;
+
+    let mut pool = new_pool_with(vec!["a", "b"], 1, fake_connect);
+    let mut tried = vec![];
+
+    // the write may have been applied before the reset, it is not run again
+    let result: std::io::Result<()> = pool.run(|s| {
+        tried.push(s.addr.clone());
+        Err(std::io::Error::new(ErrorKind::ConnectionReset, "reset"))
+    });
+
+    assert_eq!(result.unwrap_err().kind(), ErrorKind::ConnectionReset);
+    assert_eq!(tried, vec!["a"]);
+    assert!(!pool.is_up(0));
+
+    // a refused connection never carried the write, it goes to the next host
+    let mut pool = new_pool_with(vec!["a", "b"], 1, fake_connect);
+
+    let result = pool.run(|s| {
+        if s.addr == "a" {
+            Err(std::io::Error::new(ErrorKind::ConnectionRefused, "refused"))
+        } else {
+            Ok(s.addr.clone())
+        }
+    });
+
+    assert_eq!(result.unwrap(), "b");
+    assert!(!pool.is_up(0));
+}
+
+#[test]
+fn test_retry_delay() {
+    let policy = new_retry_policy();
+
+    assert_eq!(policy.delay(1), Duration::from_millis(100));
+    assert_eq!(policy.delay(2), Duration::from_millis(200));
+    assert_eq!(policy.delay(10), Duration::from_secs(2));
+
+    assert!(policy.should_retry(&Response::Error(WRITE_TIMEOUT, "timeout".to_string())));
+    assert!(!policy.should_retry(&Response::Error(0x2200, "invalid".to_string())));
+
+    // writes and reads both see the error answer as Err
+    match answered(Response::Error(WRITE_TIMEOUT, "timeout".to_string())) {
+        Err(ref e) => assert_eq!(server_error_of(e).map(|x| x.code), Some(WRITE_TIMEOUT)),
+        Ok(_) => panic!("the error answer comes back as Err")
+    }
+
+    assert!(answered(Response::Ready).is_ok());
+}
+
+fn field_def(name: &str, kind: &str, query: Option<&str>) -> FieldDef {
+    FieldDef {
+        name: name.to_string(),
+        kind: kind.to_string(),
+        query: query.map(|x| x.to_string()),
+        order: None,
+        nullable: false
+    }
+}
+
+#[test]
+fn test_conf_def() {
+    let mut def = ConfDef {
+        name: "person".to_string(),
+        fields: vec![
+            field_def("first_name", "text", Some("substring")),
+            field_def("tags", "set<text>", Some("value")),
+            field_def("home", "frozen<address>", None)
+        ],
+        by_entity: vec![],
+        by_many: vec![],
+        types: vec![UdtDef {
+            name: "address".to_string(),
+            fields: vec![field_def("city", "text", None)]
+        }],
+        ttl: None,
+        bucketing: None,
+        counters: false,
+        soft_delete: false
+    };
+
+    {
+        let c = def.to_conf().unwrap();
+        let s = c.get_schema().unwrap();
+
+        assert_eq!(s.queries[0], "create type if not exists address (city text)");
+        assert!(s.queries.iter().any(|q| q.contains("home frozen<address>,")));
+    }
+
+    def.fields.push(field_def("age", "bigint", Some("substring")));
+    assert!(def.validate().is_err());
+
+    def.fields.pop();
+    def.fields.push(field_def("tags", "text", None));
+    assert!(def.validate().is_err());
+
+    def.fields.pop();
+    def.fields.push(field_def("place", "frozen<place>", None));
+    assert!(def.validate().is_err());
+
+    def.fields.pop();
+    def.types.push(UdtDef {
+        name: "address".to_string(),
+        fields: vec![field_def("zip", "text", None)]
+    });
+    assert!(def.validate().is_err());
+
+    // address and owner use each other
+    def.types.pop();
+    def.types[0].fields.push(field_def("owner", "frozen<owner>", None));
+    def.types.push(UdtDef {
+        name: "owner".to_string(),
+        fields: vec![field_def("home", "frozen<address>", None)]
+    });
+    assert!(def.validate().is_err());
+
+    def.types[1].fields.pop();
+    assert!(def.validate().is_ok());
+
+    // columns every index table has
+    def.fields.push(field_def("created_at", "timestamp", None));
+    assert!(def.validate().is_err());
+
+    def.fields.pop();
+    def.fields.push(field_def("count", "bigint", None));
+    assert!(def.validate().is_ok());
+
+    def.counters = true;
+    assert!(def.validate().is_err());
+}
+
+#[test]
+fn test_from_row() {
+    let c = get_conf();
+
+    let mut row = HashMap::new();
+    row.insert("test1".to_string(), Column::String("asd".to_string()));
+    row.insert("test2".to_string(), Column::Timestamp(1000));
+    row.insert("test3".to_string(), Column::String("qwe".to_string()));
+
+    let (test1, test2, test3): (String, DateTime<UTC>, String) = c.decode(&row).unwrap();
+
+    assert_eq!(test1, "asd");
+    assert_eq!(test2.timestamp(), 1);
+    assert_eq!(test3, "qwe");
+
+    match c.decode::<(String, i64)>(&row) {
+        Err(Error::Type(field, _)) => assert_eq!(field, "test2"),
+        _ => panic!("test2 is a timestamp")
+    }
+
+    match c.decode::<(String, DateTime<UTC>, String, f64)>(&row) {
+        Err(Error::Missing(field)) => assert_eq!(field, "test4"),
+        _ => panic!("test4 is not in the row")
+    }
+}
+
+#[test]
+fn test_check_values() {
+    let c = get_conf();
+
+    let values = || vec![
+        Column::String("asd".to_string()),
+        Column::Bigint(1000),
+        Column::String("qwe".to_string()),
+        Column::Bigint(1),
+        now(),
+        now()
+    ];
+
+    match c.check_all_values(values(), &new_write_options()) {
+        Err(Error::Type(field, expected)) => {
+            assert_eq!(field, "test2");
+            assert_eq!(expected, "timestamp");
+        }
+        _ => panic!("test2 is a timestamp")
+    }
+
+    let mut opts = new_write_options();
+    opts.coerce = true;
+
+    let checked = c.check_all_values(values(), &opts).unwrap();
+
+    assert!(checked[1] == Column::Timestamp(1000));
+    assert!(checked[3] == Column::Double(1.0));
+
+    match c.check_all_values(vec![Column::String("asd".to_string())], &opts) {
+        Err(Error::Missing(field)) => assert_eq!(field, "test2"),
+        _ => panic!("test2 has no value")
+    }
+
+    let mut more = values();
+    more.push(Column::Bigint(1));
+
+    match c.check_all_values(more, &opts) {
+        Err(Error::Arity { expected, got }) => assert_eq!((expected, got), (6, 7)),
+        _ => panic!("there are only 6 fields")
+    }
+
+    // through io::Result and back
+    let e: std::io::Error = Error::Unknown("test9".to_string()).into();
+
+    assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput);
+
+    match Error::from(e) {
+        Error::Unknown(field) => assert_eq!(field, "test9"),
+        _ => panic!("the typed error comes back")
+    }
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn test_row_json() {
+    use proto::json::*;
+
+    let c = get_conf();
+
+    let mut row = HashMap::new();
+    row.insert("test1".to_string(), Column::String("asd".to_string()));
+    row.insert("test2".to_string(), Column::Timestamp(1500));
+    row.insert("id".to_string(), Column::Bigint(MAX_SAFE_INTEGER + 1));
+    row.insert("test5".to_string(), Column::Bigint(i64::min_value()));
+
+    let json = row_to_json(&row).unwrap();
+
+    assert_eq!(json["test2"], "1970-01-01T00:00:01.500+00:00");
+    assert_eq!(json["id"], "9007199254740992");
+    assert_eq!(json["test5"], "-9223372036854775808");
+
+    // past the dates chrono holds the milliseconds stay a number
+    row.insert("test2".to_string(), Column::Timestamp(i64::max_value()));
+
+    assert_eq!(row_to_json(&row).unwrap()["test2"], i64::max_value());
+
+    row.insert("test2".to_string(), Column::Timestamp(1500));
+    row.remove("test5");
+
+    row.remove("id");
+
+    let back = c.hashmap_from_json(&row_to_json(&row).unwrap()).unwrap();
+
+    assert_eq!(back, row);
+
+    let mut wrong = row_to_json(&row).unwrap();
+    wrong["test4"] = "1.5".into();
+
+    match c.hashmap_from_json(&wrong) {
+        Err(Error::Type(field, _)) => assert_eq!(field, "test4"),
+        _ => panic!("test4 is a double")
+    }
+}
+
+#[cfg(feature = "config")]
+#[test]
+fn test_schema_from_toml() {
+    let def = schema_from_toml("
+        [[tables]]
+        name = \"person\"
+
+        [[tables.fields]]
+        name = \"first_name\"
+        type = \"text\"
+        query = \"value\"
+        order = \"desc\"
+        ").unwrap();
+
+    let confs = def.to_confs().unwrap();
+
+    assert!(confs[0].get_schema().unwrap().queries[0].contains("clustering order by (first_name desc,id desc)"));
+
+    // options and fills only come from code, a definition can't silently drop them
+    assert!(schema_from_toml("
+        [[tables]]
+        name = \"person\"
+
+        [tables.options]
+        gc_grace_seconds = 3600
+        ").is_err());
+
+    assert!(schema_from_toml("
+        [[tables]]
+        name = \"person\"
+
+        [[tables.fields]]
+        name = \"created\"
+        type = \"timestamp\"
+        fill = \"now\"
+        ").is_err());
+}
+
+#[test]
+fn test_bulk_load() {
+    use std::io::Error as IoError;
+    use std::io::ErrorKind;
+
+    let writer = |job: BulkJob| {
+        match job.1.len() {
+            0 => Ok(()),
+            1 => Err(IoError::new(ErrorKind::Other, "timeout")),
+            _ => panic!("writer down")
+        }
+    };
+
+    let update = CounterUpdate { query: "".to_string(), values: vec![] };
+
+    let jobs = vec![
+        (1, 1, Ok((vec![], vec![]))),
+        (1, 2, Err(IoError::new(ErrorKind::InvalidInput, "bad row"))),
+        (1, 3, Ok((vec![], vec![update.clone()]))),
+        (1, 4, Ok((vec![], vec![update.clone(), update]))),
+        (1, 5, Ok((vec![], vec![]))),
+        (1, 6, Ok((vec![], vec![])))
+    ];
+
+    let mut calls = 0;
+
+    let report = bulk_load(vec![writer, writer], jobs.into_iter(), 1, &mut |_, _| calls = calls + 1);
+
+    let mut failed = report.failed.iter().map(|x| x.1).collect::<Vec<i64>>();
+
+    failed.sort();
+
+    // row 4 takes down one writer, the other one loads the rest
+    assert_eq!(report.loaded, 3);
+    assert_eq!(failed, vec![2, 3, 4]);
+    assert_eq!(calls, 6);
+
+    let report = bulk_load(vec![writer], vec![(1, 1, Ok((vec![], vec![])))].into_iter(), 0, &mut |_, _| {});
+
+    assert_eq!(report.loaded, 1);
+
+    // without writers every row fails
+    let writers: Vec<fn(BulkJob) -> std::io::Result<()>> = vec![];
+    let jobs = (1..5).map(|id| (1, id, Ok((vec![], vec![]))));
+
+    let report = bulk_load(writers, jobs, 1, &mut |_, _| {});
+
+    assert_eq!(report.loaded, 0);
+    assert_eq!(report.failed.len(), 4);
+}
+
+#[cfg(feature = "async")]
+fn simple(batch: Vec<BatchQuery>) -> Vec<(String, Vec<Column>)> {
+    batch.into_iter().map(|bq| match bq {
+        BatchQuery::SimpleWithParams(query, values) => (query, values),
+        _ => panic!("unexpected batch query")
+    }).collect()
+}
+
+// stands in for a session on the blocking pool, every read answers with rows, every write is recorded
+#[cfg(feature = "async")]
+struct Recorder {
+    rows: Vec<HashMap<String, Column>>,
+    reads: Vec<Vec<(String, Vec<Column>)>>,
+    writes: Vec<(Vec<(String, Vec<Column>)>, Vec<CounterUpdate>)>
+}
+
+#[cfg(feature = "async")]
+impl Runner for Recorder {
+    fn create_schema(&mut self, context: Vec<Schema>, _: u32) {
+        let queries = context.into_iter().flat_map(|s| s.queries.into_iter()).map(|q| (q, vec![])).collect();
+
+        self.writes.push((queries, vec![]));
+    }
+    fn select_first(&mut self, queries: Vec<(String, Vec<Column>)>) -> std::io::Result<Option<HashMap<String, Column>>> {
+        self.reads.push(queries);
+
+        Ok(self.rows.first().cloned())
+    }
+    fn select_all(&mut self, queries: Vec<(String, Vec<Column>)>) -> std::io::Result<Vec<HashMap<String, Column>>> {
+        self.reads.push(queries);
+
+        Ok(self.rows.clone())
+    }
+    fn execute_planned(&mut self, batch: Vec<BatchQuery>, consistency: Consistency, mode: &BatchMode, idempotent: bool) -> std::io::Result<Response> {
+        self.execute_with_counters(batch, vec![], consistency, mode, idempotent)
+    }
+    fn execute_with_counters(&mut self, batch: Vec<BatchQuery>, counters: Vec<CounterUpdate>, _: Consistency, _: &BatchMode, _: bool) -> std::io::Result<Response> {
+        self.writes.push((simple(batch), counters));
+
+        // any response that isn't an error
+        Ok(Response::Result(ResultBody::Prepared(vec![])))
+    }
+    fn execute_counters(&mut self, counters: Vec<CounterUpdate>, consistency: Consistency) -> std::io::Result<Response> {
+        self.execute_with_counters(vec![], counters, consistency, &BatchMode::Unlogged, false)
+    }
+}
+
+#[cfg(feature = "async")]
+fn recorder(rows: Vec<HashMap<String, Column>>) -> AsyncSession<Recorder> {
+    new_async_session(Recorder { rows: rows, reads: vec![], writes: vec![] })
+}
+
+// the async calls spawn on the blocking pool as they are made, the test enters the runtime first
+#[cfg(feature = "async")]
+fn runtime() -> ::tokio::runtime::Runtime {
+    ::tokio::runtime::Builder::new_current_thread().build().unwrap()
+}
+
+#[cfg(feature = "async")]
+fn block_on<'c, R>(f: Pending<'c, R>) -> std::io::Result<R> {
+    ::tokio::runtime::Handle::current().block_on(f)
+}
+
+#[cfg(feature = "async")]
+fn recorded(s: &AsyncSession<Recorder>) -> (Vec<Vec<(String, Vec<Column>)>>, Vec<(Vec<(String, Vec<Column>)>, Vec<CounterUpdate>)>) {
+    let mut r = s.inner.lock().unwrap();
+
+    (r.reads.drain(..).collect(), r.writes.drain(..).collect())
+}
+
+// a call that panics leaves the session unusable, later calls fail
+#[cfg(feature = "async")]
+#[test]
+fn test_async_poisoned() {
+    let rt = runtime();
+    let _rt = rt.enter();
+
+    let s = recorder(vec![]);
+
+    assert!(block_on(s.blocking(|_| -> std::io::Result<()> { panic!("in the middle of a call") })).is_err());
+    assert!(block_on(s.blocking(|r| Ok(r.reads.len()))).is_err());
+}
+
+// the async reads run the queries of the sync ones
+#[cfg(feature = "async")]
+#[test]
+fn test_async_reads() {
+    let rt = runtime();
+    let _rt = rt.enter();
+
+    let mut c = get_conf();
+
+    let mut row = HashMap::new();
+    row.insert("id".to_string(), Column::Bigint(1));
+    row.insert("test4".to_string(), Column::Double(1.5));
+
+    let s = recorder(vec![row.clone()]);
+
+    assert!(block_on(s.first(&c, 1, &c.e.test4, Column::Double(1.5))).unwrap() == Some(row.clone()));
+    assert_eq!(recorded(&s).0, vec![c.first_queries(1, &c.e.test4, Column::Double(1.5))]);
+
+    assert!(block_on(s.first_by_id(&c, 1, 1)).unwrap().is_some());
+    assert_eq!(recorded(&s).0, vec![vec![c.first_by_id_query(1, 1)]]);
+
+    assert_eq!(block_on(s.list_with_limit_by_id(&c, 1, Some(5))).unwrap().len(), 1);
+    assert_eq!(recorded(&s).0, vec![c.list_with_limit_by_id_queries(1, Some(5))]);
+
+    block_on(s.list_with_limit_by_id_with_deleted(&c, 1, None)).unwrap();
+    assert_eq!(recorded(&s).0, vec![c.list_with_limit_by_id_queries(1, None)]);
+
+    block_on(s.list_by_field(&c, 1, &c.e.test4, Some((Column::Double(1.5), 3)))).unwrap();
+    assert_eq!(recorded(&s).0, vec![c.list_by_field_queries(1, &c.e.test4, Some((Column::Double(1.5), 3)))]);
+
+    c.bucketing = Some(Bucketing::Day(7));
+
+    let from = UTC.ymd(2016, 1, 4).and_hms(0, 0, 0);
+    let to = UTC.ymd(2016, 1, 6).and_hms(0, 0, 0);
+
+    block_on(s.list_by_time(&c, 1, from, to, None)).unwrap();
+    assert_eq!(recorded(&s).0, vec![c.list_by_time_queries(1, &from, &to, None).unwrap()]);
+
+    block_on(s.list_by_time_with_deleted(&c, 1, from, to, None)).unwrap();
+    assert_eq!(recorded(&s).0, vec![c.list_by_time_queries(1, &from, &to, None).unwrap()]);
+
+    // a range longer than the lookback never reaches the session
+    let to = UTC.ymd(2016, 2, 1).and_hms(0, 0, 0);
+
+    assert!(block_on(s.list_by_time(&c, 1, from, to, None)).is_err());
+    assert!(block_on(s.list_by_time_with_deleted(&c, 1, from, to, None)).is_err());
+    assert!(recorded(&s).0.is_empty());
+
+    let mut deleted = row.clone();
+    deleted.insert("deleted_at".to_string(), Column::Timestamp(1000));
+
+    let s = recorder(vec![deleted]);
+
+    assert!(block_on(s.first_by_id(&c, 1, 1)).unwrap().is_none());
+    assert!(block_on(s.first_by_id_with_deleted(&c, 1, 1)).unwrap().is_some());
+
+    let cc = new_counter_conf("page", vec!["views", "likes"]);
+
+    let mut counts = HashMap::new();
+    counts.insert("views".to_string(), Column::Bigint(4));
+
+    let s = recorder(vec![counts]);
+
+    let read = block_on(s.read_counters(&cc, 1, 2)).unwrap().unwrap();
+
+    assert_eq!(read.get("views"), Some(&4));
+    assert_eq!(read.get("likes"), Some(&0));
+    assert_eq!(recorded(&s).0, vec![vec![cc.read_query(1, 2)]]);
+}
+
+// the async writes send the batches and counter updates of the sync ones
+#[cfg(feature = "async")]
+#[test]
+fn test_async_writes() {
+    let rt = runtime();
+    let _rt = rt.enter();
+
+    let mut c = get_conf();
+
+    c.counters = true;
+
+    let mut opts = new_write_options();
+
+    opts.timestamp = Some(1000);
+
+    let o = c.get_write_options(&opts);
+
+    let s = recorder(vec![]);
+
+    let values = vec![
+        Column::String("asd".to_string()),
+        Column::Timestamp(1000),
+        Column::String("qwe".to_string()),
+        Column::Double(1.333333),
+        Column::Timestamp(2000),
+        Column::Timestamp(3000)
+    ];
+
+    block_on(s.insert_all(&c, 1, 1, values.clone(), Quorum, &opts)).unwrap();
+
+    let mut v = values;
+    c.fill_all(&mut v);
+    let v = c.check_all_values(v, &o).unwrap();
+
+    let (reads, writes) = recorded(&s);
+
+    assert_eq!(reads, vec![vec![c.first_by_id_query(1, 1)]]);
+    assert_eq!(writes, vec![(simple(c.get_batch_for_insert_all(1, 1, v.clone(), None, &o)), c.counters_for_all(1, &None, &v))]);
+
+    let expected = {
+        let mut f = vec![&c.e.test4];
+        let mut values = c.check_values(&f, vec![Column::Double(2.5)], &o).unwrap();
+
+        c.fill(&mut f, &mut values, &[], &None);
+
+        let counters = c.counters_for(1, &None, &f, &values);
+
+        vec![(simple(c.get_batch_for_insert(1, 1, f, values, None, &o)), counters)]
+    };
+
+    block_on(s.insert(&c, 1, 1, vec![(&c.e.test4, Column::Double(2.5))], Quorum, &opts)).unwrap();
+
+    assert_eq!(recorded(&s).1, expected);
+
+    let mut hashmap = HashMap::new();
+    hashmap.insert("test4".to_string(), Column::Double(2.5));
+
+    block_on(s.insert_hashmap(&c, 1, 1, hashmap, None, Quorum, &opts)).unwrap();
+
+    assert_eq!(recorded(&s).1, expected);
+
+    let mut row = HashMap::new();
+    row.insert("id".to_string(), Column::Bigint(1));
+    row.insert("test4".to_string(), Column::Double(1.5));
+
+    let s = recorder(vec![row.clone()]);
+
+    let mut nullable = get_conf();
+
+    nullable.counters = true;
+    nullable.e.test4.nullable = true;
+
+    block_on(s.update(&nullable, 1, 1, vec![(&nullable.e.test2, FieldValue::Set(Column::Timestamp(5000))), (&nullable.e.test4, FieldValue::Null)], Quorum, &opts)).unwrap();
+
+    let expected = {
+        let (f, values, nulls) = nullable.update_values(vec![(&nullable.e.test2, FieldValue::Set(Column::Timestamp(5000))), (&nullable.e.test4, FieldValue::Null)], &o).unwrap();
+
+        let (batch, counters) = nullable.update_jobs(1, 1, f, values, nulls, Some(row.clone()), &o);
+
+        vec![(simple(batch), counters)]
+    };
+
+    assert_eq!(recorded(&s).1, expected);
+
+    assert!(block_on(s.update(&c, 1, 1, vec![(&c.e.test4, FieldValue::Null)], Quorum, &opts)).is_err());
+
+    block_on(s.delete(&c, 1, 1, Quorum, &opts)).unwrap();
+
+    assert_eq!(recorded(&s).1, vec![(simple(c.get_batch_for_delete(1, 1, Some(row.clone()), &o)), c.count_jobs(1, Some(&row), None))]);
+
+    c.soft_delete = true;
+
+    let mut deleted = row.clone();
+    deleted.insert("deleted_at".to_string(), Column::Timestamp(1000));
+
+    let s = recorder(vec![deleted.clone()]);
+
+    // the deleted row is read and counts as no row
+    block_on(s.insert_all(&c, 1, 1, v.clone(), Quorum, &opts)).unwrap();
+
+    let (reads, writes) = recorded(&s);
+
+    assert_eq!(reads, vec![vec![c.first_by_id_query(1, 1)]]);
+    assert_eq!(writes, vec![(simple(c.get_batch_for_insert_all(1, 1, v.clone(), None, &o)), c.counters_for_all(1, &None, &v))]);
+
+    block_on(s.purge(&c, 1, 1, Quorum, &opts)).unwrap();
+
+    assert_eq!(recorded(&s).1, vec![(simple(c.get_batch_for_purge(1, 1, Some(deleted.clone()), &o)), vec![])]);
+
+    block_on(s.restore(&c, 1, 1, Quorum, &opts)).unwrap();
+
+    assert_eq!(recorded(&s).1, vec![(simple(c.get_batch_for_restore(1, 1, deleted.clone(), &o)), c.count_jobs(1, None, Some(&deleted)))]);
+
+    // a deleted row can't be deleted again
+    assert!(block_on(s.delete(&c, 1, 1, Quorum, &opts)).is_err());
+
+    let tagged = new_conf("tagged", (), Some(vec![
+        new_fc(Field::Text("title"), QueryType::Storaged),
+        new_fc(Field::Set("tags", CqlType::Text), QueryType::Value)
+        ]), None, None);
+
+    let tags = &tagged.fields.as_ref().unwrap()[1];
+    let items = Column::Set(vec![Column::String("a".to_string())]);
+
+    let s = recorder(vec![]);
+
+    block_on(s.append(&tagged, 1, 1, tags, items.clone(), Quorum, &opts)).unwrap();
+
+    let (reads, writes) = recorded(&s);
+
+    // an indexed set reads the row for its index rows
+    assert_eq!(reads, vec![vec![tagged.first_by_id_query(1, 1)]]);
+    assert_eq!(writes, vec![(simple(tagged.get_batch_for_append(1, 1, tags, items.clone(), None, &o)), vec![])]);
+
+    block_on(s.remove(&tagged, 1, 1, tags, items.clone(), Quorum, &opts)).unwrap();
+
+    assert_eq!(recorded(&s), (vec![vec![tagged.first_by_id_query(1, 1)]], vec![(simple(tagged.get_batch_for_remove(1, 1, tags, items, None, &o)), vec![])]));
+
+    let cc = new_counter_conf("page", vec!["views"]);
+
+    block_on(s.increment(&cc, 1, 2, "views", 3, Quorum)).unwrap();
+    block_on(s.decrement(&cc, 1, 2, "views", 3, Quorum)).unwrap();
+
+    assert_eq!(recorded(&s).1, vec![
+        (vec![], vec![cc.get_update(1, 2, vec![("views", 3)]).unwrap()]),
+        (vec![], vec![cc.get_update(1, 2, vec![("views", -3)]).unwrap()])
+    ]);
+
+    assert!(block_on(s.decrement(&cc, 1, 2, "views", ::std::i64::MIN, Quorum)).is_err());
+    assert!(block_on(s.add(&cc, 1, 2, vec![("shares", 1)], Quorum)).is_err());
+    assert!(recorded(&s).1.is_empty());
+
+    block_on(s.create_schema(vec![tagged.get_schema().unwrap()], 1)).unwrap();
+
+    assert_eq!(recorded(&s).1[0].0.len(), tagged.get_schema().unwrap().queries.len());
+}
@@ -0,0 +1,225 @@
+use std::result;
+
+use rustcql::shared::Column;
+
+use proto::shared::*;
+use proto::schema::Schema;
+use proto::error::Error;
+
+
+// user-defined types declared on a Conf, emitted by get_schema before the tables
+// (udt f1 f2 ... fn)                                       frozen<udt>
+
+pub struct Udt<'a> {
+    pub name: &'a str,
+    pub fields: Vec<Field<'a>>
+}
+
+pub fn new_udt<'a>(name: &'a str, fields: Vec<Field<'a>>) -> Udt<'a> {
+    Udt {
+        name: name,
+        fields: fields
+    }
+}
+
+impl<'a> Udt<'a> {
+    pub fn get_udts(&self) -> Vec<&'a str> {
+        let mut res = vec![];
+
+        for f in self.fields.iter() {
+            res.extend(f.get_udts());
+        }
+
+        res
+    }
+}
+
+pub fn udt_create_job(schema: &mut Schema, udt: &Udt) {
+    let mut query = udt.fields.iter().fold("create type if not exists ".to_string() + udt.name + " (", |query, x| {
+        add_field(query, x)
+    });
+
+    let len = query.len();
+
+    query.truncate(len - 1);
+
+    query = query + ")";
+
+    //println!("");
+    //println!("{}", query);
+
+    schema.queries.push(query);
+}
+
+// types come out after every declared type they use
+pub fn types_create_job(schema: &mut Schema, types: &Vec<Udt>) -> result::Result<(), Error> {
+    for t in try!(types_in_order(types)) {
+        udt_create_job(schema, t);
+    }
+
+    Ok(())
+}
+
+// declared types, each after the declared types it uses, types outside the list are left to the server
+pub fn types_in_order<'b, 'a>(types: &'b Vec<Udt<'a>>) -> result::Result<Vec<&'b Udt<'a>>, Error> {
+    let mut done: Vec<&Udt> = vec![];
+
+    while done.len() < types.len() {
+        let before = done.len();
+
+        for t in types.iter() {
+            if done.iter().any(|x| x.name == t.name) {
+                continue;
+            }
+
+            let ready = t.get_udts().iter().all(|x| done.iter().any(|y| y.name == *x) || !types.iter().any(|y| y.name == *x));
+
+            if ready {
+                done.push(t);
+            }
+        }
+
+        if done.len() == before {
+            let t = types.iter().find(|t| !done.iter().any(|x| x.name == t.name)).unwrap();
+
+            return Err(Error::Cycle(t.name.to_string()));
+        }
+    }
+
+    Ok(done)
+}
+
+// nested values for Field::Udt and collections of udts, bound through fromJson(?)
+
+pub enum Nested<'a> {
+    Value(Column),
+    List(Vec<Nested<'a>>),
+    Map(Vec<(Nested<'a>, Nested<'a>)>),
+    Udt(Vec<(&'a str, Nested<'a>)>)
+}
+
+impl<'a> Nested<'a> {
+    pub fn to_json(&self) -> result::Result<String, Error> {
+        match self {
+            &Nested::Value(ref c) => column_to_json(c),
+            &Nested::List(ref items) => {
+                let mut res = vec![];
+                for x in items.iter() {
+                    res.push(try!(x.to_json()));
+                }
+                Ok("[".to_string() + &res.join(",") + "]")
+            }
+            &Nested::Map(ref items) => {
+                // json object keys are strings, fromJson parses them back into the key type
+                let mut res = vec![];
+                for &(ref k, ref v) in items.iter() {
+                    let key = match k {
+                        &Nested::Value(Column::String(ref s)) => s.clone(),
+                        _ => try!(k.to_json())
+                    };
+                    res.push(quote(&key) + ":" + &try!(v.to_json()));
+                }
+                Ok("{".to_string() + &res.join(",") + "}")
+            }
+            &Nested::Udt(ref fields) => {
+                let mut res = vec![];
+                for &(name, ref v) in fields.iter() {
+                    res.push(quote(name) + ":" + &try!(v.to_json()));
+                }
+                Ok("{".to_string() + &res.join(",") + "}")
+            }
+        }
+    }
+    pub fn to_column(&self) -> result::Result<Column, Error> {
+        self.to_json().map(Column::String)
+    }
+}
+
+pub fn column_to_json(c: &Column) -> result::Result<String, Error> {
+    match c {
+        &Column::String(ref s) => Ok(quote(s)),
+        &Column::Bigint(v) => Ok(v.to_string()),
+        &Column::Timestamp(v) => Ok(v.to_string()),
+        &Column::Double(v) => Ok(v.to_string()),
+        &Column::List(ref items) | &Column::Set(ref items) => {
+            let mut res = vec![];
+            for x in items.iter() {
+                res.push(try!(column_to_json(x)));
+            }
+            Ok("[".to_string() + &res.join(",") + "]")
+        }
+        _ => Err(Error::Nested(format!("{:?}", c)))
+    }
+}
+
+// collections of udts travel as columns with one json text per udt, so sets still fan out per element,
+// they are bound as a single json text for fromJson(?)
+pub fn bind_nested(f: &Field, c: Column) -> Column {
+    let json = match (f, &c) {
+        _ if !f.is_nested() => None,
+        (&Field::List(_, ref t), &Column::List(ref items)) | (&Field::Set(_, ref t), &Column::Set(ref items)) => Some(elements_json(t, items)),
+        // the keys a map remove takes out
+        (&Field::Map(_, ref k, _), &Column::Set(ref items)) => Some(elements_json(k, items)),
+        (&Field::Map(_, ref k, ref v), &Column::Map(ref items)) => Some(map_json(k, v, items)),
+        _ => None
+    };
+
+    // unchecked values go as they are, the server rejects them
+    match json {
+        Some(Ok(json)) => Column::String(json),
+        _ => c
+    }
+}
+
+pub fn bind_values(f: &[&FieldConf], values: Vec<Column>) -> Vec<Column> {
+    f.iter().zip(values.into_iter()).map(|(fc, c)| bind_nested(&fc.f, c)).collect()
+}
+
+fn elements_json(t: &CqlType, items: &Vec<Column>) -> result::Result<String, Error> {
+    let mut res = vec![];
+    for x in items.iter() {
+        res.push(try!(element_json(t, x)));
+    }
+    Ok("[".to_string() + &res.join(",") + "]")
+}
+
+// a udt element already is json
+fn element_json(t: &CqlType, c: &Column) -> result::Result<String, Error> {
+    match (t, c) {
+        (&CqlType::Udt(_), &Column::String(ref s)) => Ok(s.clone()),
+        _ => column_to_json(c)
+    }
+}
+
+fn map_json(k: &CqlType, v: &CqlType, items: &Vec<(Column, Column)>) -> result::Result<String, Error> {
+    let mut res = vec![];
+    for &(ref key, ref value) in items.iter() {
+        // json object keys are strings, fromJson parses them back into the key type
+        let key = match key {
+            &Column::String(ref s) => s.clone(),
+            _ => try!(element_json(k, key))
+        };
+        res.push(quote(&key) + ":" + &try!(element_json(v, value)));
+    }
+    Ok("{".to_string() + &res.join(",") + "}")
+}
+
+fn quote(s: &str) -> String {
+    let mut res = "\"".to_string();
+
+    for ch in s.chars() {
+        match ch {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '\n' => res.push_str("\\n"),
+            '\r' => res.push_str("\\r"),
+            '\t' => res.push_str("\\t"),
+            c if (c as u32) < 0x20 => res.push_str(&format!("\\u{:04x}", c as u32)),
+            c => res.push(c)
+        }
+    }
+
+    res.push('"');
+
+    res
+}
@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::hash::Hash;
+
+use chrono::*;
+
+use rustcql::shared::Column;
+
+use proto::shared::*;
+
+
+// conversions between rust values and columns, used by the code cqlapi_derive generates
+
+pub trait ToColumn {
+    fn to_column(&self) -> Column;
+}
+
+pub trait FromColumn: Sized {
+    fn from_column(c: &Column) -> Option<Self>;
+}
+
+// types that can be elements of lists, sets and maps
+pub trait ElementType {
+    fn cql_type() -> CqlType<'static>;
+}
+
+// types that can be fields, Key is what a _by_field_ table is looked up by
+pub trait FieldType {
+    type Key: ToColumn;
+    // sets are indexed one row per element
+    const FANNED_OUT: bool = false;
+    fn field<'a>(name: &'a str) -> Field<'a>;
+}
+
+impl ToColumn for i64 {
+    fn to_column(&self) -> Column {
+        Column::Bigint(*self)
+    }
+}
+
+impl FromColumn for i64 {
+    fn from_column(c: &Column) -> Option<i64> {
+        match c {
+            &Column::Bigint(v) => Some(v),
+            _ => None
+        }
+    }
+}
+
+impl ElementType for i64 {
+    fn cql_type() -> CqlType<'static> {
+        CqlType::Bigint
+    }
+}
+
+impl FieldType for i64 {
+    type Key = i64;
+    fn field<'a>(name: &'a str) -> Field<'a> {
+        Field::Bigint(name)
+    }
+}
+
+impl ToColumn for f64 {
+    fn to_column(&self) -> Column {
+        Column::Double(*self)
+    }
+}
+
+impl FromColumn for f64 {
+    fn from_column(c: &Column) -> Option<f64> {
+        match c {
+            &Column::Double(v) => Some(v),
+            _ => None
+        }
+    }
+}
+
+impl ElementType for f64 {
+    fn cql_type() -> CqlType<'static> {
+        CqlType::Double
+    }
+}
+
+impl FieldType for f64 {
+    type Key = f64;
+    fn field<'a>(name: &'a str) -> Field<'a> {
+        Field::Double(name)
+    }
+}
+
+impl ToColumn for String {
+    fn to_column(&self) -> Column {
+        Column::String(self.clone())
+    }
+}
+
+impl<'b> ToColumn for &'b str {
+    fn to_column(&self) -> Column {
+        Column::String(self.to_string())
+    }
+}
+
+impl FromColumn for String {
+    fn from_column(c: &Column) -> Option<String> {
+        match c {
+            &Column::String(ref v) => Some(v.clone()),
+            _ => None
+        }
+    }
+}
+
+impl ElementType for String {
+    fn cql_type() -> CqlType<'static> {
+        CqlType::Text
+    }
+}
+
+impl FieldType for String {
+    type Key = String;
+    fn field<'a>(name: &'a str) -> Field<'a> {
+        Field::Text(name)
+    }
+}
+
+impl ToColumn for DateTime<UTC> {
+    fn to_column(&self) -> Column {
+        Column::Timestamp(self.timestamp() * 1000 + (self.nanosecond() / 1000000) as i64)
+    }
+}
+
+impl FromColumn for DateTime<UTC> {
+    fn from_column(c: &Column) -> Option<DateTime<UTC>> {
+        timestamp_of(c).map(|ms| UTC.timestamp(ms.div_euclid(1000), (ms.rem_euclid(1000) * 1000000) as u32))
+    }
+}
+
+impl ElementType for DateTime<UTC> {
+    fn cql_type() -> CqlType<'static> {
+        CqlType::Timestamp
+    }
+}
+
+impl FieldType for DateTime<UTC> {
+    type Key = DateTime<UTC>;
+    fn field<'a>(name: &'a str) -> Field<'a> {
+        Field::Timestamp(name)
+    }
+}
+
+impl<T: ToColumn> ToColumn for Vec<T> {
+    fn to_column(&self) -> Column {
+        Column::List(self.iter().map(|x| x.to_column()).collect())
+    }
+}
+
+impl<T: FromColumn> FromColumn for Vec<T> {
+    fn from_column(c: &Column) -> Option<Vec<T>> {
+        match c {
+            &Column::List(ref items) => items.iter().map(|x| T::from_column(x)).collect(),
+            _ => None
+        }
+    }
+}
+
+impl<T: ElementType + ToColumn> FieldType for Vec<T> {
+    type Key = Vec<T>;
+    fn field<'a>(name: &'a str) -> Field<'a> {
+        Field::List(name, T::cql_type())
+    }
+}
+
+impl<T: ToColumn + Eq + Hash> ToColumn for HashSet<T> {
+    fn to_column(&self) -> Column {
+        Column::Set(self.iter().map(|x| x.to_column()).collect())
+    }
+}
+
+impl<T: FromColumn + Eq + Hash> FromColumn for HashSet<T> {
+    fn from_column(c: &Column) -> Option<HashSet<T>> {
+        match c {
+            &Column::Set(ref items) => items.iter().map(|x| T::from_column(x)).collect(),
+            _ => None
+        }
+    }
+}
+
+impl<T: ElementType + ToColumn + Eq + Hash> FieldType for HashSet<T> {
+    type Key = T;
+    const FANNED_OUT: bool = true;
+    fn field<'a>(name: &'a str) -> Field<'a> {
+        Field::Set(name, T::cql_type())
+    }
+}
+
+impl<K: ToColumn + Eq + Hash, V: ToColumn> ToColumn for HashMap<K, V> {
+    fn to_column(&self) -> Column {
+        Column::Map(self.iter().map(|(k, v)| (k.to_column(), v.to_column())).collect())
+    }
+}
+
+impl<K: FromColumn + Eq + Hash, V: FromColumn> FromColumn for HashMap<K, V> {
+    fn from_column(c: &Column) -> Option<HashMap<K, V>> {
+        match c {
+            &Column::Map(ref items) => items.iter().map(|&(ref k, ref v)| {
+                match (K::from_column(k), V::from_column(v)) {
+                    (Some(k), Some(v)) => Some((k, v)),
+                    _ => None
+                }
+            }).collect(),
+            _ => None
+        }
+    }
+}
+
+impl<K: ElementType + ToColumn + Eq + Hash, V: ElementType + ToColumn> FieldType for HashMap<K, V> {
+    type Key = HashMap<K, V>;
+    fn field<'a>(name: &'a str) -> Field<'a> {
+        Field::Map(name, K::cql_type(), V::cql_type())
+    }
+}
+
+// milliseconds of a timestamp column, for i64 fields marked #[cql(timestamp)]
+pub fn timestamp_of(c: &Column) -> Option<i64> {
+    match c {
+        &Column::Timestamp(v) => Some(v),
+        _ => None
+    }
+}